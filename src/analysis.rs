@@ -0,0 +1,442 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::{error::Error, fmt};
+
+use crate::grammar::{Grammar, Mode};
+use crate::symbols::{Symbol, SymbolId};
+use crate::table::GrammarError;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Validation {
+    // Nonterminals used without any rule defining them
+    pub undefined: Vec<SymbolId>,
+    // Nonterminals with rules that the start symbol never derives
+    pub unreachable: Vec<SymbolId>,
+}
+
+impl Validation {
+    pub fn is_ok(&self) -> bool {
+        self.undefined.is_empty() && self.unreachable.is_empty()
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    // Every terminal, the end marker included
+    pub terminals: Vec<SymbolId>,
+    pub nonterminals: Vec<SymbolId>,
+    pub rules: usize,
+    // Symbols defined by rules that no rule uses, like the start symbol
+    pub only_lhs: Vec<SymbolId>,
+    // Symbols used by rules without rules of their own, like the terminals
+    pub only_rhs: Vec<SymbolId>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StartError {
+    // Every nonterminal shows up on some rhs
+    NoCandidate,
+    // Several nonterminals never show up on a rhs
+    Ambiguous(Vec<SymbolId>),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoCandidate => write!(f, "no nonterminal can be the start symbol"),
+            Self::Ambiguous(candidates) => {
+                write!(
+                    f,
+                    "{} nonterminals can be the start symbol",
+                    candidates.len()
+                )
+            }
+        }
+    }
+}
+
+impl Error for StartError {}
+
+// The smallest of the table constructions that builds without conflicts, each
+// class holding the ones before it
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum GrammarClass {
+    Lr0,
+    Slr1,
+    Lalr1,
+    Lr1,
+    // Ambiguous, or needing more than one token of lookahead
+    NotLr1,
+}
+
+impl Grammar {
+    // The declared start symbol, or else the one nonterminal that only its
+    // own rules use, for `build` when the start symbol is not given, so a left
+    // recursive start is still found. The S' symbols of earlier builds do not
+    // count.
+    pub fn infer_start(&self) -> Result<Symbol, StartError> {
+        if let Some(start) = self.declared_start() {
+            return Ok(start.clone());
+        }
+
+        let used: BTreeSet<SymbolId> = self
+            .rules
+            .iter()
+            .filter(|&(&lhs, _)| !self.is_augmented(lhs))
+            .flat_map(|(&lhs, rules)| {
+                rules
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .filter(move |&symbol| symbol != lhs)
+            })
+            .collect();
+        let candidates: Vec<SymbolId> = self
+            .rules
+            .keys()
+            .copied()
+            .filter(|&symbol| !used.contains(&symbol) && !self.is_augmented(symbol))
+            .collect::<BTreeSet<SymbolId>>()
+            .into_iter()
+            .collect();
+
+        match candidates.as_slice() {
+            [] => Err(StartError::NoCandidate),
+            &[start] => Ok(self.symbols.collection[start].clone()),
+            _ => Err(StartError::Ambiguous(candidates)),
+        }
+    }
+
+    // The grammar as written, the S' symbols and rules builds add are left
+    // out so the summary is the same before and after one
+    pub fn summary(&self) -> Summary {
+        let (terminals, nonterminals): (Vec<SymbolId>, Vec<SymbolId>) = (0..self.symbols.len())
+            .filter(|&symbol| !self.is_augmented(symbol))
+            .partition(|&symbol| self.symbols.is_terminal(symbol));
+
+        let rules = self.written_rules();
+        let lhs: BTreeSet<SymbolId> = rules.clone().map(|(lhs, _)| lhs).collect();
+        let rhs: BTreeSet<SymbolId> = rules.flat_map(|(_, rhs)| rhs).flatten().copied().collect();
+
+        Summary {
+            terminals,
+            nonterminals,
+            rules: self
+                .rules_lhs
+                .iter()
+                .filter(|&&lhs| !self.is_augmented(lhs))
+                .count(),
+            only_lhs: lhs.difference(&rhs).copied().collect(),
+            only_rhs: rhs.difference(&lhs).copied().collect(),
+        }
+    }
+
+    pub(crate) fn reachable_from(&self, start: SymbolId) -> BTreeSet<SymbolId> {
+        let mut reachable = BTreeSet::from([start]);
+        let mut to_visit = Vec::from([start]);
+
+        while let Some(symbol) = to_visit.pop() {
+            for rhs in self.rules.get(&symbol).into_iter().flatten() {
+                for &rhs_symbol in rhs {
+                    if reachable.insert(rhs_symbol) {
+                        to_visit.push(rhs_symbol);
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+
+    // Tries LR(0), SLR(1), LALR(1) and LR(1) in turn. Precedence and the
+    // conflict policy settle conflicts as they do for `build`, so a grammar
+    // they make buildable counts as being in the class. Errors other than
+    // conflicts stop the search.
+    pub fn classify(&mut self, start: Symbol) -> Result<GrammarClass, GrammarError> {
+        let classes = [
+            (Mode::Lr0, GrammarClass::Lr0),
+            (Mode::Slr1, GrammarClass::Slr1),
+            (Mode::Lalr1, GrammarClass::Lalr1),
+            (Mode::Lr1, GrammarClass::Lr1),
+        ];
+        for (mode, class) in classes {
+            match self.build(start.clone(), mode) {
+                Ok(_) => return Ok(class),
+                Err(GrammarError::Conflicts(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(GrammarClass::NotLr1)
+    }
+
+    pub fn validate(&self, start: &Symbol) -> Validation {
+        let start = match self.symbols.id(start) {
+            Some(start) => start,
+            None => return Validation::default(),
+        };
+
+        let used = self
+            .written_rules()
+            .flat_map(|(_, rules)| rules)
+            .flatten()
+            .copied()
+            .chain([start]);
+        let undefined: BTreeSet<SymbolId> = used
+            .filter(|&symbol| self.symbols.is_nonterminal(symbol))
+            .filter(|symbol| !self.rules.contains_key(symbol))
+            .collect();
+
+        let unreachable: Vec<SymbolId> = self
+            .unreachable_from(start)
+            .into_iter()
+            .filter(|symbol| self.rules.contains_key(symbol))
+            .collect();
+
+        Validation {
+            undefined: undefined.into_iter().collect(),
+            unreachable,
+        }
+    }
+
+    // The rules as written, without the S' rules builds add
+    fn written_rules(&self) -> impl Iterator<Item = (SymbolId, &Vec<Vec<SymbolId>>)> + Clone {
+        self.rules
+            .iter()
+            .map(|(&lhs, rules)| (lhs, rules))
+            .filter(|&(lhs, _)| !self.is_augmented(lhs))
+    }
+
+    // Symbols mentioned by the rules, the end marker is never one of them
+    fn used_symbols(&self) -> BTreeSet<SymbolId> {
+        self.written_rules()
+            .flat_map(|(lhs, rules)| rules.iter().flatten().copied().chain([lhs]))
+            .collect()
+    }
+
+    fn unreachable_from(&self, start: SymbolId) -> Vec<SymbolId> {
+        let reachable = self.reachable_from(start);
+
+        self.used_symbols()
+            .into_iter()
+            .filter(|symbol| !reachable.contains(symbol))
+            .collect()
+    }
+
+    // Terminals and nonterminals that never show up in a derivation from `start`
+    pub fn unreachable_symbols(&self, start: &Symbol) -> Vec<SymbolId> {
+        match self.symbols.id(start) {
+            Some(start) => self.unreachable_from(start),
+            None => self.used_symbols().into_iter().collect(),
+        }
+    }
+
+    // Nonterminals that derive ε, through an empty rule or a rule made only
+    // of nullable nonterminals
+    pub fn nullable(&self) -> BTreeSet<SymbolId> {
+        let mut nullable: BTreeSet<SymbolId> = BTreeSet::new();
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (&lhs, rules) in &self.rules {
+                if nullable.contains(&lhs) {
+                    continue;
+                }
+
+                if rules
+                    .iter()
+                    .any(|rhs| rhs.iter().all(|symbol| nullable.contains(symbol)))
+                {
+                    nullable.insert(lhs);
+                    changed = true;
+                }
+            }
+        }
+
+        nullable
+    }
+
+    // Nonterminals that cannot derive any string made only of terminals
+    pub fn unproductive_symbols(&self) -> Vec<SymbolId> {
+        let mut productive: BTreeSet<SymbolId> = BTreeSet::new();
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (&lhs, rules) in &self.rules {
+                if productive.contains(&lhs) {
+                    continue;
+                }
+
+                let derives_terminals = rules.iter().any(|rhs| {
+                    rhs.iter().all(|symbol| {
+                        self.symbols.is_terminal(*symbol) || productive.contains(symbol)
+                    })
+                });
+                if derives_terminals {
+                    productive.insert(lhs);
+                    changed = true;
+                }
+            }
+        }
+
+        self.used_symbols()
+            .into_iter()
+            .filter(|&symbol| self.symbols.is_nonterminal(symbol))
+            .filter(|symbol| !productive.contains(symbol))
+            .collect()
+    }
+
+    // Some nonterminals A, B, ... with A ⇒+ B ⇒+ ... ⇒+ A, each deriving the
+    // next one alone, which makes every sentence of A ambiguous. A rule
+    // A -> α B β takes A to B when α and β are nullable. The cycle starts at
+    // its lowest nonterminal and is the shortest from it.
+    pub fn has_cycle(&self) -> Option<Vec<SymbolId>> {
+        let nullable = self.nullable();
+
+        let mut units: HashMap<SymbolId, BTreeSet<SymbolId>> = HashMap::new();
+        for (&lhs, rules) in &self.rules {
+            for rhs in rules {
+                for (position, &symbol) in rhs.iter().enumerate() {
+                    let others_nullable = rhs
+                        .iter()
+                        .enumerate()
+                        .all(|(other, symbol)| other == position || nullable.contains(symbol));
+                    if self.symbols.is_nonterminal(symbol) && others_nullable {
+                        units.entry(lhs).or_default().insert(symbol);
+                    }
+                }
+            }
+        }
+
+        let mut nonterminals: Vec<SymbolId> = units.keys().copied().collect();
+        nonterminals.sort_unstable();
+        for nonterminal in nonterminals {
+            // Breadth first, so the way back found first is a shortest one
+            let mut parents: HashMap<SymbolId, SymbolId> = HashMap::new();
+            let mut to_visit: VecDeque<SymbolId> = VecDeque::from([nonterminal]);
+
+            while let Some(symbol) = to_visit.pop_front() {
+                for &next in units.get(&symbol).into_iter().flatten() {
+                    if next == nonterminal {
+                        let mut cycle = Vec::from([symbol]);
+                        while let Some(&parent) = parents.get(cycle.last().unwrap()) {
+                            cycle.push(parent);
+                        }
+                        cycle.reverse();
+                        return Some(cycle);
+                    }
+                    if next != nonterminal && !parents.contains_key(&next) {
+                        parents.insert(next, symbol);
+                        to_visit.push_back(next);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    // Nonterminals A with A ⇒+ A α, directly or through other nonterminals
+    pub fn left_recursive_nonterminals(&self) -> Vec<SymbolId> {
+        self.recursive_nonterminals(false)
+    }
+
+    // Nonterminals A with A ⇒+ α A
+    pub fn right_recursive_nonterminals(&self) -> Vec<SymbolId> {
+        self.recursive_nonterminals(true)
+    }
+
+    // Left recursive nonterminals whose recursion goes past a nullable prefix,
+    // like A in A -> B A with B -> ε. Such grammars are ambiguous or
+    // conflict for every LR(k), usually by mistake.
+    pub fn hidden_left_recursion(&self) -> Vec<SymbolId> {
+        let nullable = self.nullable();
+
+        // A to B, and whether some rule gets there past a nullable prefix
+        let mut dependencies: HashMap<SymbolId, HashMap<SymbolId, bool>> = HashMap::new();
+        for (&lhs, rules) in &self.rules {
+            for rhs in rules {
+                for (position, &symbol) in rhs.iter().enumerate() {
+                    if self.symbols.is_nonterminal(symbol) {
+                        let hidden = dependencies
+                            .entry(lhs)
+                            .or_default()
+                            .entry(symbol)
+                            .or_default();
+                        *hidden |= position > 0;
+                    }
+                    if !nullable.contains(&symbol) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut recursive: BTreeSet<SymbolId> = BTreeSet::new();
+        for &nonterminal in dependencies.keys() {
+            // Nonterminals reached, and whether a hidden step was taken
+            let mut visited: BTreeSet<(SymbolId, bool)> = BTreeSet::new();
+            let mut to_visit: Vec<(SymbolId, bool)> = Vec::from([(nonterminal, false)]);
+
+            while let Some((symbol, hidden)) = to_visit.pop() {
+                for (&next, &step_hidden) in dependencies.get(&symbol).into_iter().flatten() {
+                    let hidden = hidden || step_hidden;
+                    if next == nonterminal && hidden {
+                        recursive.insert(nonterminal);
+                    }
+                    if visited.insert((next, hidden)) {
+                        to_visit.push((next, hidden));
+                    }
+                }
+            }
+        }
+
+        recursive.into_iter().collect()
+    }
+
+    // A depends on B when some rule of A has B at its left (or right) end once
+    // the nullable symbols next to it are skipped, A is recursive when it
+    // depends on itself through some chain of those
+    fn recursive_nonterminals(&self, right: bool) -> Vec<SymbolId> {
+        let nullable = self.nullable();
+
+        let mut dependencies: HashMap<SymbolId, BTreeSet<SymbolId>> = HashMap::new();
+        for (&lhs, rules) in &self.rules {
+            for rhs in rules {
+                let mut symbols = rhs.clone();
+                if right {
+                    symbols.reverse();
+                }
+
+                for symbol in symbols {
+                    if self.symbols.is_nonterminal(symbol) {
+                        dependencies.entry(lhs).or_default().insert(symbol);
+                    }
+                    if !nullable.contains(&symbol) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut recursive: BTreeSet<SymbolId> = BTreeSet::new();
+        for &nonterminal in dependencies.keys() {
+            let mut visited: BTreeSet<SymbolId> = BTreeSet::new();
+            let mut to_visit: Vec<SymbolId> = dependencies[&nonterminal].iter().copied().collect();
+
+            while let Some(symbol) = to_visit.pop() {
+                if symbol == nonterminal {
+                    recursive.insert(nonterminal);
+                    break;
+                }
+                if visited.insert(symbol) {
+                    to_visit.extend(dependencies.get(&symbol).into_iter().flatten());
+                }
+            }
+        }
+
+        recursive.into_iter().collect()
+    }
+}
@@ -0,0 +1,735 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use crate::grammar::{RuleId, Span};
+use crate::item::{Item, core_key, union_lookaheads};
+use crate::precedence::{ConflictPolicy, Precedence, Resolution, ResolvedConflict};
+use crate::symbols::{Symbol, SymbolId, Symbols};
+use crate::table::{Action, Conflict, ParseTable};
+
+pub(crate) type Transitions = HashMap<(usize, SymbolId), usize>;
+
+// The shortest symbols taking one of the first `roots` states to each state,
+// a viable prefix of every item in it
+pub(crate) fn prefixes(
+    states: usize,
+    transitions: &Transitions,
+    roots: usize,
+) -> Vec<Option<Vec<SymbolId>>> {
+    let mut edges: Vec<Vec<(SymbolId, usize)>> = vec![Vec::new(); states];
+    for (&(from, symbol), &to) in transitions {
+        edges[from].push((symbol, to));
+    }
+
+    let mut prefixes: Vec<Option<Vec<SymbolId>>> = (0..states)
+        .map(|state| (state < roots).then(Vec::new))
+        .collect();
+    let mut to_visit: VecDeque<usize> = (0..roots).collect();
+
+    while let Some(state) = to_visit.pop_front() {
+        edges[state].sort();
+        for &(symbol, next_state) in &edges[state] {
+            if prefixes[next_state].is_none() {
+                let mut prefix = prefixes[state].clone().unwrap_or_default();
+                prefix.push(symbol);
+                prefixes[next_state] = Some(prefix);
+                to_visit.push_back(next_state);
+            }
+        }
+    }
+
+    prefixes
+}
+
+pub(crate) struct BuiltActions {
+    pub(crate) actions: Vec<HashMap<SymbolId, Action>>,
+    // Conflicts left to the user
+    pub(crate) conflicts: Vec<Conflict>,
+    // Conflicts the policy settled
+    pub(crate) defaulted: Vec<Conflict>,
+    pub(crate) resolved: Vec<ResolvedConflict>,
+}
+
+// `reduce_on` gives the lookaheads a completed item reduces on, reduce
+// actions carry the number of the rule in `rule_ids`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn build_actions(
+    states: &[BTreeSet<Item>],
+    transitions: &Transitions,
+    symbols: &Symbols,
+    starts: &[SymbolId],
+    precedence: &Precedence,
+    policy: ConflictPolicy,
+    spans: &HashMap<RuleId, Span>,
+    rule_ids: &[RuleId],
+    reduce_on: impl Fn(&Item) -> Vec<SymbolId>,
+) -> BuiltActions {
+    let mut actions: Vec<HashMap<SymbolId, Action>> = Vec::new();
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let mut defaulted: Vec<Conflict> = Vec::new();
+    let mut resolved: Vec<ResolvedConflict> = Vec::new();
+    let prefixes = prefixes(states.len(), transitions, starts.len());
+    let numbers: HashMap<&RuleId, usize> = rule_ids
+        .iter()
+        .enumerate()
+        .map(|(number, rule)| (rule, number))
+        .collect();
+
+    for (state, set) in states.iter().enumerate() {
+        let mut new_actions: HashMap<SymbolId, Action> = HashMap::new();
+        let mut collisions: Vec<(SymbolId, Action, Action, &RuleId)> = Vec::new();
+        let mut reductions: HashMap<SymbolId, &RuleId> = HashMap::new();
+        let mut reduce_collisions: Vec<(SymbolId, RuleId, RuleId)> = Vec::new();
+
+        // The S' items accept first and no reduce takes their place. Another
+        // reduce on $ next to S' -> S · would need S ⇒+ S, which
+        // `Grammar::has_cycle` warns about.
+        for item in set
+            .iter()
+            .filter(|item| item.end() && starts.contains(&item.rule.lhs))
+        {
+            for lookahead in reduce_on(item) {
+                new_actions.insert(lookahead, Action::Accept);
+            }
+        }
+
+        for item in set {
+            if !item.end() || starts.contains(&item.rule.lhs) {
+                continue;
+            }
+
+            for lookahead in reduce_on(item) {
+                if new_actions.get(&lookahead) == Some(&Action::Accept) {
+                    continue;
+                }
+                if let Some(&existing) = reductions.get(&lookahead) {
+                    reduce_collisions.push((lookahead, existing.clone(), item.rule.clone()));
+                    if !policy.prefers_first_rule() || numbers[existing] < numbers[&item.rule] {
+                        continue;
+                    }
+                }
+
+                reductions.insert(lookahead, &item.rule);
+                new_actions.insert(lookahead, Action::Reduce(numbers[&item.rule]));
+            }
+        }
+
+        let mut shifts: Vec<(SymbolId, usize)> = transitions
+            .iter()
+            .filter(|&(&(from, _), _)| from == state)
+            .map(|(&(_, symbol_id), &next_state)| (symbol_id, next_state))
+            .collect();
+        shifts.sort();
+
+        for (symbol_id, next_state) in shifts {
+            let action = match symbols.collection[symbol_id] {
+                Symbol::Terminal(_) => Action::Shift(next_state),
+                Symbol::Nonterminal(_) => Action::Goto(next_state),
+            };
+
+            let (existing, &rule) = match (new_actions.get(&symbol_id), reductions.get(&symbol_id))
+            {
+                (Some(existing), Some(rule)) => (existing, rule),
+                _ => {
+                    new_actions.insert(symbol_id, action);
+                    continue;
+                }
+            };
+
+            let resolution = match precedence.resolve(rule, symbol_id) {
+                Some(resolution) => resolution,
+                None => {
+                    collisions.push((symbol_id, existing.clone(), action.clone(), rule));
+                    if policy.prefers_shift() {
+                        new_actions.insert(symbol_id, action);
+                    }
+                    continue;
+                }
+            };
+            if let (Some((rule_level, _)), Some((symbol_level, associativity))) =
+                (precedence.rule(rule), precedence.terminal(symbol_id))
+            {
+                resolved.push(ResolvedConflict {
+                    state,
+                    symbol: symbol_id,
+                    rule: rule.clone(),
+                    rule_level,
+                    symbol_level,
+                    associativity,
+                    resolution,
+                });
+            }
+            match resolution {
+                Resolution::Shift => {
+                    new_actions.insert(symbol_id, action);
+                }
+                Resolution::Reduce => {}
+                Resolution::Error => {
+                    new_actions.remove(&symbol_id);
+                }
+            }
+        }
+
+        let span = |rule: &RuleId| spans.get(rule).copied();
+        let items: Vec<Item> = set
+            .iter()
+            .filter(|item| item.position > 0 || starts.contains(&item.rule.lhs))
+            .cloned()
+            .collect();
+        let prefix = prefixes[state].clone().unwrap_or_default();
+        let shift_reduce = match policy.prefers_shift() {
+            true => &mut defaulted,
+            false => &mut conflicts,
+        };
+        for (symbol_id, existing, rejected, rule) in collisions {
+            shift_reduce.push(Conflict::ShiftReduce {
+                state,
+                symbol: symbols.display_name(symbol_id).into_owned(),
+                actions: Vec::from([existing, rejected]),
+                spans: span(rule).into_iter().collect(),
+                items: items.clone(),
+                prefix: prefix.clone(),
+            });
+        }
+        let reduce_reduce = match policy.prefers_first_rule() {
+            true => &mut defaulted,
+            false => &mut conflicts,
+        };
+        for (symbol_id, existing, rejected) in reduce_collisions {
+            let reduced_on = |rule: &RuleId| -> BTreeSet<SymbolId> {
+                set.iter()
+                    .filter(|item| item.end() && item.rule == *rule)
+                    .flat_map(&reduce_on)
+                    .collect()
+            };
+            let lookaheads = &reduced_on(&existing) & &reduced_on(&rejected);
+            let rules = Vec::from([existing, rejected]);
+            reduce_reduce.push(Conflict::ReduceReduce {
+                state,
+                symbol: symbols.display_name(symbol_id).into_owned(),
+                spans: rules.iter().filter_map(span).collect(),
+                rules,
+                lookaheads,
+                items: items.clone(),
+                prefix: prefix.clone(),
+            });
+        }
+
+        actions.push(new_actions);
+    }
+
+    BuiltActions {
+        actions,
+        conflicts,
+        defaulted,
+        resolved,
+    }
+}
+
+// LALR(1), states whose items only differ in their lookaheads become one
+pub(crate) fn merge_cores(
+    states: Vec<BTreeSet<Item>>,
+    transitions: Transitions,
+) -> (Vec<BTreeSet<Item>>, Transitions) {
+    let mut cores: HashMap<BTreeSet<(RuleId, usize)>, usize> = HashMap::new();
+    let mut renumbered: Vec<usize> = Vec::new();
+
+    for set in &states {
+        let core: BTreeSet<(RuleId, usize)> = set.iter().map(Item::core).collect();
+        let next = cores.len();
+        renumbered.push(*cores.entry(core).or_insert(next));
+    }
+
+    merge_groups(states, transitions, &renumbered)
+}
+
+// Minimal LR(1), states with the same core become one only when that brings
+// no reduce/reduce conflict the canonical states did not have. Shifts only
+// depend on the core, so merging cannot bring shift/reduce conflicts. Two
+// merged states take their successors on each symbol along, so each merge is
+// tried with all those it takes, and left undone if any of them conflicts.
+pub(crate) fn merge_compatible(
+    states: Vec<BTreeSet<Item>>,
+    transitions: Transitions,
+) -> (Vec<BTreeSet<Item>>, Transitions) {
+    let mut groups: Vec<usize> = (0..states.len()).collect();
+    // The states seen so far by core, as the first state of their group
+    let mut cores: HashMap<BTreeSet<(RuleId, usize)>, Vec<usize>> = HashMap::new();
+
+    for state in 0..states.len() {
+        // Taken along by an earlier merge
+        if group_of(&groups, state) != state {
+            continue;
+        }
+        let core: BTreeSet<(RuleId, usize)> = states[state].iter().map(Item::core).collect();
+        let candidates = cores.entry(core).or_default();
+
+        let merged = candidates
+            .iter()
+            .find_map(|&candidate| try_merge(&states, &transitions, &groups, state, candidate));
+        match merged {
+            Some(merged) => groups = merged,
+            None => candidates.push(state),
+        }
+    }
+
+    let mut numbers: HashMap<usize, usize> = HashMap::new();
+    let renumbered: Vec<usize> = (0..states.len())
+        .map(|state| {
+            let next = numbers.len();
+            *numbers.entry(group_of(&groups, state)).or_insert(next)
+        })
+        .collect();
+
+    merge_groups(states, transitions, &renumbered)
+}
+
+// A group is named after its first state, which is its own group
+fn group_of(groups: &[usize], mut state: usize) -> usize {
+    while groups[state] != state {
+        state = groups[state];
+    }
+    state
+}
+
+// The groups after merging `first` and `second` with their successors, `None`
+// if that puts two reductions on the same lookahead where none of the merged
+// states had both
+fn try_merge(
+    states: &[BTreeSet<Item>],
+    transitions: &Transitions,
+    groups: &[usize],
+    first: usize,
+    second: usize,
+) -> Option<Vec<usize>> {
+    let mut groups = groups.to_vec();
+    let mut pairs: Vec<(usize, usize)> = Vec::from([(first, second)]);
+    let mut merged: Vec<usize> = Vec::new();
+
+    while let Some((first, second)) = pairs.pop() {
+        let (first_group, second_group) = (group_of(&groups, first), group_of(&groups, second));
+        if first_group == second_group {
+            continue;
+        }
+        groups[first_group.max(second_group)] = first_group.min(second_group);
+        merged.push(first_group.min(second_group));
+
+        for item in &states[first] {
+            let Some(symbol) = item.next_symbol() else {
+                continue;
+            };
+            if let (Some(&to_first), Some(&to_second)) = (
+                transitions.get(&(first, symbol)),
+                transitions.get(&(second, symbol)),
+            ) {
+                pairs.push((to_first, to_second));
+            }
+        }
+    }
+
+    let mut checked: BTreeSet<usize> = BTreeSet::new();
+    for group in merged {
+        let group = group_of(&groups, group);
+        if !checked.insert(group) {
+            continue;
+        }
+        let members = (0..states.len()).filter(|&state| group_of(&groups, state) == group);
+        if !reductions_compatible(members.map(|state| &states[state])) {
+            return None;
+        }
+    }
+    Some(groups)
+}
+
+fn reductions_compatible<'a>(sets: impl Iterator<Item = &'a BTreeSet<Item>>) -> bool {
+    let mut reductions: HashMap<SymbolId, BTreeSet<&RuleId>> = HashMap::new();
+    // Lookaheads some state reduces more than one rule on already
+    let mut conflicting: BTreeSet<SymbolId> = BTreeSet::new();
+
+    for set in sets {
+        let mut own: HashMap<SymbolId, BTreeSet<&RuleId>> = HashMap::new();
+        for item in set.iter().filter(|item| item.end()) {
+            for &lookahead in &item.lookaheads {
+                own.entry(lookahead).or_default().insert(&item.rule);
+            }
+        }
+        for (lookahead, rules) in own {
+            if rules.len() > 1 {
+                conflicting.insert(lookahead);
+            }
+            reductions.entry(lookahead).or_default().extend(rules);
+        }
+    }
+
+    reductions
+        .iter()
+        .all(|(lookahead, rules)| rules.len() < 2 || conflicting.contains(lookahead))
+}
+
+// The states of each group become one with the union of their lookaheads,
+// `renumbered[state]` being the group of `state`, numbered from 0 in order
+fn merge_groups(
+    states: Vec<BTreeSet<Item>>,
+    transitions: Transitions,
+    renumbered: &[usize],
+) -> (Vec<BTreeSet<Item>>, Transitions) {
+    let mut merged: Vec<BTreeSet<Item>> = Vec::new();
+
+    for (set, &state) in states.into_iter().zip(renumbered) {
+        if state == merged.len() {
+            merged.push(set);
+        } else {
+            let items = std::mem::take(&mut merged[state]);
+            merged[state] = union_lookaheads(items.into_iter().chain(set));
+        }
+    }
+
+    let transitions = transitions
+        .into_iter()
+        .map(|((from, symbol_id), to)| ((renumbered[from], symbol_id), renumbered[to]))
+        .collect();
+
+    (merged, transitions)
+}
+
+// The canonical collection behind a table, `states` is indexed by state number
+pub struct ItemGraph {
+    pub states: Vec<BTreeSet<Item>>,
+    pub transitions: HashMap<(usize, SymbolId), usize>,
+}
+
+impl ParseTable {
+    // The states and every edge of the collection, including the shifts a
+    // settled conflict took out of the table
+    pub fn item_graph(&self) -> ItemGraph {
+        ItemGraph {
+            states: self.states.clone(),
+            transitions: self.transitions.clone(),
+        }
+    }
+
+    // The edges the table still takes, the shifts and gotos of `actions`
+    pub(crate) fn action_transitions(&self) -> Transitions {
+        let mut transitions = Transitions::new();
+
+        for (state, actions) in self.actions.iter().enumerate() {
+            for (&symbol, action) in actions {
+                if let Action::Shift(next_state) | Action::Goto(next_state) = *action {
+                    transitions.insert((state, symbol), next_state);
+                }
+            }
+        }
+
+        transitions
+    }
+
+    // States grouped by their cores, each group and the groups in state order.
+    // The groups with more than one state are the ones LALR(1) would merge.
+    pub fn states_by_core(&self) -> Vec<Vec<usize>> {
+        let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let states = &self.states;
+        let core = |state: usize| -> BTreeSet<(RuleId, usize)> {
+            states[state].iter().map(Item::core).collect()
+        };
+
+        for (state, set) in states.iter().enumerate() {
+            let bucket = buckets.entry(core_key(set)).or_default();
+            let group = bucket
+                .iter()
+                .copied()
+                .find(|&group| core(groups[group][0]) == core(state));
+
+            match group {
+                Some(group) => groups[group].push(state),
+                None => {
+                    bucket.push(groups.len());
+                    groups.push(Vec::from([state]));
+                }
+            }
+        }
+
+        groups
+    }
+
+    // Whether states `a` and `b` have the same core and the union of their
+    // lookaheads reduces no two rules on the same terminal, unless one of them
+    // did already. Only the two states are looked at, merging them for real
+    // merges their successors too, see `merge_compatible`.
+    pub fn can_merge(&self, a: usize, b: usize) -> bool {
+        let (Some(first), Some(second)) = (self.states.get(a), self.states.get(b)) else {
+            return false;
+        };
+        let core = |set: &BTreeSet<Item>| -> BTreeSet<(RuleId, usize)> {
+            set.iter().map(Item::core).collect()
+        };
+
+        core(first) == core(second) && reductions_compatible([first, second].into_iter())
+    }
+
+    // The shortest symbols that take a start state to `state`, the shifts and
+    // gotos that explain why it exists. Empty for a start state, `None` for a
+    // state past the last one.
+    pub fn path_to(&self, state: usize) -> Option<Vec<SymbolId>> {
+        prefixes(self.states_len(), &self.transitions, self.starts.len())
+            .get(state)
+            .cloned()
+            .flatten()
+    }
+
+    // The shortest terminals that take `state` to accepting, none when the
+    // state cannot be reached or nothing completes it. The stack below
+    // `state` is the one of its shortest prefix. Stacks that grow by more than
+    // there are states only repeat a part that a shorter completion skips, so
+    // they are not followed.
+    pub fn shortest_completion(&self, state: usize) -> Option<Vec<SymbolId>> {
+        let transitions = self.action_transitions();
+        let prefix = prefixes(self.states_len(), &transitions, self.starts.len())
+            .get(state)
+            .cloned()
+            .flatten()?;
+        let stack = (0..self.starts.len()).find_map(|root| {
+            let mut stack = Vec::from([root]);
+            for &symbol in &prefix {
+                stack.push(*transitions.get(&(stack[stack.len() - 1], symbol))?);
+            }
+            (stack[stack.len() - 1] == state).then_some(stack)
+        })?;
+
+        let max_depth = stack.len() + self.states_len();
+        let terminals: Vec<SymbolId> = (0..self.symbols.len())
+            .filter(|&symbol| self.symbols.is_terminal(symbol))
+            .collect();
+        let mut visited: BTreeSet<Vec<usize>> = BTreeSet::from([stack.clone()]);
+        let mut to_visit: VecDeque<(Vec<usize>, Vec<SymbolId>)> =
+            VecDeque::from([(stack, Vec::new())]);
+
+        while let Some((stack, completion)) = to_visit.pop_front() {
+            for &terminal in &terminals {
+                // Reductions on `terminal` until it is shifted or accepted
+                let mut stack = stack.clone();
+                loop {
+                    match self.action(stack[stack.len() - 1], terminal) {
+                        Some(&Action::Reduce(rule)) => {
+                            stack.truncate(stack.len() - self.rules_len[rule]);
+                            match self.goto(stack[stack.len() - 1], self.rules_lhs[rule]) {
+                                Some(next_state) => stack.push(next_state),
+                                None => break,
+                            }
+                        }
+                        Some(&Action::Shift(next_state)) => {
+                            stack.push(next_state);
+                            let mut completion = completion.clone();
+                            completion.push(terminal);
+                            if stack.len() <= max_depth && visited.insert(stack.clone()) {
+                                to_visit.push_back((stack, completion));
+                            }
+                            break;
+                        }
+                        Some(Action::Accept) => return Some(completion),
+                        Some(Action::Goto(_)) | None => break,
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    // `numbers[state]` becomes the number of `state`, the numbers are the
+    // states in some other order
+    pub(crate) fn renumber(&mut self, numbers: &[usize]) {
+        let mut states: Vec<BTreeSet<Item>> = vec![BTreeSet::new(); self.states.len()];
+        for (state, set) in std::mem::take(&mut self.states).into_iter().enumerate() {
+            states[numbers[state]] = set;
+        }
+        self.states = states;
+
+        let mut actions: Vec<HashMap<SymbolId, Action>> = vec![HashMap::new(); self.actions.len()];
+        for (state, mut row) in std::mem::take(&mut self.actions).into_iter().enumerate() {
+            for action in row.values_mut() {
+                if let Action::Shift(next_state) | Action::Goto(next_state) = action {
+                    *next_state = numbers[*next_state];
+                }
+            }
+            actions[numbers[state]] = row;
+        }
+        self.actions = actions;
+
+        self.transitions = std::mem::take(&mut self.transitions)
+            .into_iter()
+            .map(|((state, symbol), next_state)| ((numbers[state], symbol), numbers[next_state]))
+            .collect();
+
+        for resolved in &mut self.resolved {
+            resolved.state = numbers[resolved.state];
+        }
+        self.resolved.sort_by_key(|resolved| resolved.state);
+        for conflict in &mut self.defaulted {
+            let (Conflict::ShiftReduce { state, .. } | Conflict::ReduceReduce { state, .. }) =
+                conflict;
+            *state = numbers[*state];
+        }
+    }
+
+    // Gives the states the numbers they had in `previous`, matching them by
+    // their kernels, or else by the cores of their kernels when only
+    // lookaheads changed. The others take the numbers left over.
+    pub(crate) fn keep_numbers(&mut self, previous: &ParseTable) {
+        let core = |kernel: &BTreeSet<Item>| -> BTreeSet<(RuleId, usize)> {
+            kernel.iter().map(Item::core).collect()
+        };
+
+        let states_len = self.states_len();
+        let kernels: Vec<BTreeSet<Item>> =
+            (0..states_len).map(|state| self.kernel(state)).collect();
+        let mut previous_kernels: HashMap<BTreeSet<Item>, usize> = HashMap::new();
+        let mut previous_cores: HashMap<BTreeSet<(RuleId, usize)>, Vec<usize>> = HashMap::new();
+        for state in 0..previous.states_len() {
+            if state < states_len {
+                let kernel = previous.kernel(state);
+                previous_cores.entry(core(&kernel)).or_default().push(state);
+                previous_kernels.insert(kernel, state);
+            }
+        }
+
+        let mut numbers: Vec<Option<usize>> = vec![None; states_len];
+        let mut taken = vec![false; states_len];
+        for (state, kernel) in kernels.iter().enumerate() {
+            if let Some(&number) = previous_kernels.get(kernel) {
+                numbers[state] = Some(number);
+                taken[number] = true;
+            }
+        }
+        for (state, kernel) in kernels.iter().enumerate() {
+            if numbers[state].is_some() {
+                continue;
+            }
+            let candidates = previous_cores.get(&core(kernel)).into_iter().flatten();
+            if let Some(&number) = candidates.into_iter().find(|&&number| !taken[number]) {
+                numbers[state] = Some(number);
+                taken[number] = true;
+            }
+        }
+
+        let mut free = (0..states_len).filter(|&number| !taken[number]);
+        let numbers: Vec<usize> = numbers
+            .into_iter()
+            .map(|number| {
+                number
+                    .or_else(|| free.next())
+                    .expect("more states than numbers")
+            })
+            .collect();
+        self.renumber(&numbers);
+    }
+
+    // Turns a canonical LR(1) table into an LALR(1) one. A conflict free LR(1)
+    // table can only become conflicting through merging, and then only with
+    // reduce/reduce conflicts, which are what gets reported.
+    pub fn merge_cores(&self) -> Result<ParseTable, Vec<Conflict>> {
+        let (states, transitions) = merge_cores(self.states.clone(), self.transitions.clone());
+
+        let built = build_actions(
+            &states,
+            &transitions,
+            &self.symbols,
+            &self.augmented(),
+            &self.precedence,
+            self.policy,
+            &self.spans,
+            &self.rule_ids,
+            |item| item.lookaheads.iter().copied().collect(),
+        );
+
+        if !built.conflicts.is_empty() {
+            return Err(built.conflicts);
+        }
+
+        Ok(ParseTable {
+            symbols: self.symbols.clone(),
+            end: self.end,
+            starts: self.starts.clone(),
+            precedence: self.precedence.clone(),
+            policy: self.policy,
+            spans: self.spans.clone(),
+            labels: self.labels.clone(),
+            states,
+            transitions,
+            actions: built.actions,
+            rules_lhs: self.rules_lhs.clone(),
+            rules_len: self.rules_len.clone(),
+            rule_ids: self.rule_ids.clone(),
+            resolved: built.resolved,
+            defaulted: built.defaulted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::Mode;
+    use crate::symbols::Symbol;
+    use crate::table::{Action, GrammarError, ParseTable};
+    use crate::text::parse_grammar;
+
+    fn build(text: &str, mode: Mode) -> ParseTable {
+        parse_grammar(text)
+            .unwrap()
+            .build(Symbol::Nonterminal("E".to_string()), mode)
+            .unwrap()
+    }
+
+    #[test]
+    fn path_to_the_number_shift() {
+        let table = build("E -> E plus T | T\nT -> number", Mode::Lalr1);
+        let number = table.symbols().id_by_name("number").unwrap();
+        let Some(&Action::Shift(state)) = table.action(0, number) else {
+            panic!("state 0 does not shift number");
+        };
+
+        assert_eq!(table.path_to(state), Some(vec![number]));
+        assert_eq!(table.path_to(0), Some(Vec::new()));
+        assert_eq!(table.path_to(99), None);
+        assert_eq!(table.shortest_completion(99), None);
+        assert!(table.kernel(99).is_empty());
+    }
+
+    // The dragon book grammar 4.58, merging the two states reached on `c`
+    // makes `A -> c` and `B -> c` both reduce on `d` and on `e`
+    #[test]
+    fn lr1_builds_what_lalr_cannot() {
+        let text = "S -> a A d | b B d | a B e | b A e\nA -> c\nB -> c";
+        let start = Symbol::Nonterminal("S".to_string());
+
+        let lalr = parse_grammar(text)
+            .unwrap()
+            .build(start.clone(), Mode::Lalr1);
+        assert!(matches!(lalr, Err(GrammarError::Conflicts(_))));
+
+        let table = parse_grammar(text)
+            .unwrap()
+            .build(start, Mode::Lr1)
+            .unwrap();
+        assert_eq!(table.states_len(), 14);
+        let id = |name| table.symbols().id_by_name(name).unwrap();
+        assert!(table.recognizes(&[id("a"), id("c"), id("e")]));
+        assert!(table.recognizes(&[id("b"), id("c"), id("d")]));
+        assert!(!table.recognizes(&[id("a"), id("c"), id("c")]));
+    }
+
+    // Minimal LR(1) merges like LALR where that brings no conflict, and keeps
+    // every LR(1) state apart where it would
+    #[test]
+    fn minimal_lr1_merges_only_without_conflicts() {
+        for (text, states) in [
+            ("S -> C C\nC -> c C | d", 7),
+            ("S -> a A d | b B d | a B e | b A e\nA -> c\nB -> c", 14),
+        ] {
+            let table = parse_grammar(text)
+                .unwrap()
+                .build(Symbol::Nonterminal("S".to_string()), Mode::MinimalLr1)
+                .unwrap();
+            assert_eq!(table.states_len(), states, "{text:?}");
+        }
+    }
+}
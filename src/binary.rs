@@ -0,0 +1,520 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::{error::Error, fmt};
+
+use crate::automaton::Transitions;
+use crate::grammar::{RuleId, Span};
+use crate::item::Item;
+use crate::precedence::{Associativity, ConflictPolicy, Precedence};
+use crate::symbols::{Symbol, SymbolId, Symbols};
+use crate::table::{Action, ParseTable};
+
+// Tables as bytes, for targets without serde
+//
+// Every number is an unsigned LEB128 varint: 7 bits per byte from the lowest,
+// the high bit set on every byte but the last, so numbers below 128 take one
+// byte. A string is its length and as many UTF-8 bytes. After the magic
+// `LRGT` and the version byte come, in order:
+//
+//     symbols     count, then per symbol its kind (0 terminal, 1 nonterminal,
+//                 2 end marker) and name
+//     literals    count, then terminal and text, patterns likewise
+//     codes       count, then terminal and code
+//     starts      count, then start symbol and S'
+//     rules       count, then lhs, rhs length and rhs, by rule number
+//     spans       per rule, 0 and index or 1, line and column
+//     labels      count, then rule and label
+//     precedence  count of levels, then associativity (0 left, 1 right,
+//                 2 nonassoc), count of terminals and terminals, then count
+//                 of rules with a precedence of their own, rule and terminal
+//     policy      0 report, 1 prefer shift, 2 prefer first rule, 3 yacc
+//     states      count, then per state its count of items and items as rule,
+//                 position, count of lookaheads and lookaheads
+//     actions     per state, count of entries, then symbol and action sorted
+//                 by symbol. The action is its argument shifted left by 2 over
+//                 a tag: 0 shift, 1 goto, 2 reduce, 3 accept.
+//     transitions per state, count of edges, then symbol and next state
+//                 sorted by symbol
+//
+// Whatever is sorted is sorted by id, so one table always gives the same
+// bytes. The conflicts precedence or the policy settled are left out, a table
+// read back has none.
+const MAGIC: &[u8; 4] = b"LRGT";
+const VERSION: u8 = 3;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    // The bytes do not start with `LRGT`
+    Magic,
+    // Written by a version this one cannot read
+    Version(u8),
+    // The bytes end in the middle of the table
+    Truncated,
+    // Some part of the table does not make sense, like an action going to a
+    // state past the last one
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Magic => write!(f, "not a parse table"),
+            Self::Version(version) => write!(f, "unsupported table version {version}"),
+            Self::Truncated => write!(f, "truncated parse table"),
+            Self::Invalid(part) => write!(f, "invalid {part} in parse table"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+    write_varint(bytes, text.len());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(DecodeError::Truncated)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<usize, DecodeError> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = usize::from(byte & 0x7f);
+            if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                return Err(DecodeError::Invalid("number"));
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    // A varint that has to be below `limit`, an id or a count of what follows
+    fn below(&mut self, limit: usize, part: &'static str) -> Result<usize, DecodeError> {
+        let value = self.varint()?;
+        if value < limit {
+            Ok(value)
+        } else {
+            Err(DecodeError::Invalid(part))
+        }
+    }
+
+    // Counts can be no larger than the bytes left, which keeps a corrupt
+    // count from reserving a huge vector
+    fn count(&mut self) -> Result<usize, DecodeError> {
+        let count = self.varint()?;
+        if count > self.bytes.len() - self.position {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.count()?;
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::Invalid("name"))
+    }
+}
+
+impl ParseTable {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(*MAGIC);
+        bytes.push(VERSION);
+        let rule_numbers: HashMap<&RuleId, usize> = self
+            .rule_ids
+            .iter()
+            .enumerate()
+            .map(|(number, rule)| (rule, number))
+            .collect();
+
+        write_varint(&mut bytes, self.symbols.len());
+        for (id, symbol) in self.symbols.export() {
+            let (kind, name) = match &symbol {
+                _ if self.symbols.is_end(id) => (2, "$"),
+                Symbol::Terminal(name) => (0, name.as_str()),
+                Symbol::Nonterminal(name) => (1, name.as_str()),
+            };
+            write_varint(&mut bytes, kind);
+            write_string(&mut bytes, name);
+        }
+        for text_of in [Symbols::literal, Symbols::pattern] {
+            let texts: Vec<(SymbolId, &str)> = (0..self.symbols.len())
+                .filter_map(|id| Some((id, text_of(&self.symbols, id)?)))
+                .collect();
+            write_varint(&mut bytes, texts.len());
+            for (terminal, text) in texts {
+                write_varint(&mut bytes, terminal);
+                write_string(&mut bytes, text);
+            }
+        }
+        let codes: Vec<(SymbolId, u32)> = (0..self.symbols.len())
+            .filter_map(|id| Some((id, self.symbols.code(id)?)))
+            .collect();
+        write_varint(&mut bytes, codes.len());
+        for (terminal, code) in codes {
+            write_varint(&mut bytes, terminal);
+            write_varint(&mut bytes, code as usize);
+        }
+
+        write_varint(&mut bytes, self.starts.len());
+        for &(start, augmented) in &self.starts {
+            write_varint(&mut bytes, start);
+            write_varint(&mut bytes, augmented);
+        }
+
+        write_varint(&mut bytes, self.rule_ids.len());
+        for rule in &self.rule_ids {
+            write_varint(&mut bytes, rule.lhs);
+            write_varint(&mut bytes, rule.rhs.len());
+            for &symbol in &rule.rhs {
+                write_varint(&mut bytes, symbol);
+            }
+        }
+        for (number, rule) in self.rule_ids.iter().enumerate() {
+            match self.spans.get(rule) {
+                Some(&Span::Text { line, column }) => {
+                    write_varint(&mut bytes, 1);
+                    write_varint(&mut bytes, line);
+                    write_varint(&mut bytes, column);
+                }
+                Some(&Span::Index(index)) => {
+                    write_varint(&mut bytes, 0);
+                    write_varint(&mut bytes, index);
+                }
+                None => {
+                    write_varint(&mut bytes, 0);
+                    write_varint(&mut bytes, number);
+                }
+            }
+        }
+
+        let mut labels: Vec<(usize, &str)> = self
+            .labels
+            .iter()
+            .filter_map(|(rule, label)| Some((*rule_numbers.get(rule)?, label.as_str())))
+            .collect();
+        labels.sort();
+        write_varint(&mut bytes, labels.len());
+        for (rule, label) in labels {
+            write_varint(&mut bytes, rule);
+            write_string(&mut bytes, label);
+        }
+
+        let levels = self.precedence.levels();
+        write_varint(&mut bytes, levels.len());
+        for (associativity, terminals) in levels {
+            let associativity = match associativity {
+                Associativity::Left => 0,
+                Associativity::Right => 1,
+                Associativity::Nonassoc => 2,
+            };
+            write_varint(&mut bytes, associativity);
+            write_varint(&mut bytes, terminals.len());
+            for terminal in terminals {
+                write_varint(&mut bytes, terminal);
+            }
+        }
+        let mut rule_precedence: Vec<(usize, SymbolId)> = self
+            .precedence
+            .rules
+            .iter()
+            .filter_map(|(rule, &terminal)| Some((*rule_numbers.get(rule)?, terminal)))
+            .collect();
+        rule_precedence.sort();
+        write_varint(&mut bytes, rule_precedence.len());
+        for (rule, terminal) in rule_precedence {
+            write_varint(&mut bytes, rule);
+            write_varint(&mut bytes, terminal);
+        }
+
+        let policy = match self.policy {
+            ConflictPolicy::Report => 0,
+            ConflictPolicy::PreferShift => 1,
+            ConflictPolicy::PreferFirstRule => 2,
+            ConflictPolicy::Yacc => 3,
+        };
+        write_varint(&mut bytes, policy);
+
+        let states = &self.states;
+        write_varint(&mut bytes, states.len());
+        for set in states {
+            write_varint(&mut bytes, set.len());
+            for item in set {
+                write_varint(&mut bytes, rule_numbers[&item.rule]);
+                write_varint(&mut bytes, item.position);
+                write_varint(&mut bytes, item.lookaheads.len());
+                for &lookahead in &item.lookaheads {
+                    write_varint(&mut bytes, lookahead);
+                }
+            }
+        }
+
+        for actions in &self.actions {
+            let mut actions: Vec<(&SymbolId, &Action)> = actions.iter().collect();
+            actions.sort_by_key(|&(&symbol, _)| symbol);
+            write_varint(&mut bytes, actions.len());
+            for (&symbol, action) in actions {
+                let packed = match *action {
+                    Action::Shift(state) => state << 2,
+                    Action::Goto(state) => (state << 2) | 1,
+                    Action::Reduce(rule) => (rule << 2) | 2,
+                    Action::Accept => 3,
+                };
+                write_varint(&mut bytes, symbol);
+                write_varint(&mut bytes, packed);
+            }
+        }
+
+        let mut transitions: Vec<Vec<(SymbolId, usize)>> = vec![Vec::new(); states.len()];
+        for (&(state, symbol), &next_state) in &self.transitions {
+            transitions[state].push((symbol, next_state));
+        }
+        for mut edges in transitions {
+            edges.sort();
+            write_varint(&mut bytes, edges.len());
+            for (symbol, next_state) in edges {
+                write_varint(&mut bytes, symbol);
+                write_varint(&mut bytes, next_state);
+            }
+        }
+
+        bytes
+    }
+
+    // A table `to_bytes` wrote, as it was but for the settled conflicts
+    pub fn from_bytes(bytes: &[u8]) -> Result<ParseTable, DecodeError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(DecodeError::Magic);
+        }
+        let mut reader = Reader {
+            bytes,
+            position: MAGIC.len(),
+        };
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(DecodeError::Version(version));
+        }
+
+        let symbols_len = reader.count()?;
+        let mut exported: Vec<(SymbolId, Symbol)> = Vec::with_capacity(symbols_len);
+        let mut end = None;
+        for id in 0..symbols_len {
+            let kind = reader.below(3, "symbol kind")?;
+            let name = reader.string()?;
+            match kind {
+                0 => exported.push((id, Symbol::Terminal(name))),
+                1 => exported.push((id, Symbol::Nonterminal(name))),
+                _ if end.is_none() => {
+                    end = Some(id);
+                    exported.push((id, Symbol::Terminal(name)));
+                }
+                _ => return Err(DecodeError::Invalid("end marker")),
+            }
+        }
+        let mut symbols = Symbols::import(&exported, end).ok_or(DecodeError::Invalid("symbol"))?;
+        let end = end.ok_or(DecodeError::Invalid("end marker"))?;
+        for set_text in [Symbols::set_literal, Symbols::set_pattern] {
+            for _ in 0..reader.count()? {
+                let terminal = reader.below(symbols_len, "symbol")?;
+                let text = reader.string()?;
+                set_text(&mut symbols, terminal, &text);
+            }
+        }
+        for _ in 0..reader.count()? {
+            let terminal = reader.below(symbols_len, "symbol")?;
+            let code = u32::try_from(reader.varint()?).map_err(|_| DecodeError::Invalid("code"))?;
+            symbols.set_code(terminal, code);
+        }
+
+        let mut starts: Vec<(SymbolId, SymbolId)> = Vec::new();
+        for _ in 0..reader.count()? {
+            let start = reader.below(symbols_len, "symbol")?;
+            let augmented = reader.below(symbols_len, "symbol")?;
+            starts.push((start, augmented));
+        }
+        if starts.is_empty() {
+            return Err(DecodeError::Invalid("start"));
+        }
+
+        let mut rule_ids: Vec<RuleId> = Vec::new();
+        for _ in 0..reader.count()? {
+            let lhs = reader.below(symbols_len, "symbol")?;
+            let mut rhs: Vec<SymbolId> = Vec::new();
+            for _ in 0..reader.count()? {
+                rhs.push(reader.below(symbols_len, "symbol")?);
+            }
+            rule_ids.push(RuleId { lhs, rhs });
+        }
+        let mut spans: HashMap<RuleId, Span> = HashMap::new();
+        for rule in &rule_ids {
+            let span = match reader.below(2, "span")? {
+                0 => Span::Index(reader.varint()?),
+                _ => Span::Text {
+                    line: reader.varint()?,
+                    column: reader.varint()?,
+                },
+            };
+            spans.insert(rule.clone(), span);
+        }
+        let mut labels: HashMap<RuleId, String> = HashMap::new();
+        for _ in 0..reader.count()? {
+            let rule = rule_ids[reader.below(rule_ids.len(), "rule")?].clone();
+            labels.insert(rule, reader.string()?);
+        }
+
+        let mut precedence = Precedence::default();
+        for _ in 0..reader.count()? {
+            let associativity = match reader.below(3, "associativity")? {
+                0 => Associativity::Left,
+                1 => Associativity::Right,
+                _ => Associativity::Nonassoc,
+            };
+            let mut terminals: Vec<SymbolId> = Vec::new();
+            for _ in 0..reader.count()? {
+                terminals.push(reader.below(symbols_len, "symbol")?);
+            }
+            precedence.declare(associativity, &terminals);
+        }
+        for _ in 0..reader.count()? {
+            let rule = reader.below(rule_ids.len(), "rule")?;
+            let terminal = reader.below(symbols_len, "symbol")?;
+            precedence.rules.insert(rule_ids[rule].clone(), terminal);
+        }
+
+        let policy = match reader.below(4, "policy")? {
+            0 => ConflictPolicy::Report,
+            1 => ConflictPolicy::PreferShift,
+            2 => ConflictPolicy::PreferFirstRule,
+            _ => ConflictPolicy::Yacc,
+        };
+
+        let states_len = reader.count()?;
+        let mut states: Vec<BTreeSet<Item>> = Vec::new();
+        let mut seen: HashSet<BTreeSet<Item>> = HashSet::new();
+        for _ in 0..states_len {
+            let mut set: BTreeSet<Item> = BTreeSet::new();
+            for _ in 0..reader.count()? {
+                let rule = rule_ids[reader.below(rule_ids.len(), "rule")?].clone();
+                let position = reader.below(rule.rhs.len() + 1, "item")?;
+                let mut lookaheads: BTreeSet<SymbolId> = BTreeSet::new();
+                for _ in 0..reader.count()? {
+                    lookaheads.insert(reader.below(symbols_len, "symbol")?);
+                }
+                set.insert(Item {
+                    rule,
+                    lookaheads,
+                    position,
+                });
+            }
+            if !seen.insert(set.clone()) {
+                return Err(DecodeError::Invalid("state"));
+            }
+            states.push(set);
+        }
+        if starts.len() > states_len {
+            return Err(DecodeError::Invalid("start"));
+        }
+
+        let mut actions: Vec<HashMap<SymbolId, Action>> = Vec::with_capacity(states_len);
+        for _ in 0..states_len {
+            let mut row: HashMap<SymbolId, Action> = HashMap::new();
+            for _ in 0..reader.count()? {
+                let symbol = reader.below(symbols_len, "symbol")?;
+                let packed = reader.varint()?;
+                let (tag, argument) = (packed & 3, packed >> 2);
+                let action = match tag {
+                    0 if argument < states_len => Action::Shift(argument),
+                    1 if argument < states_len => Action::Goto(argument),
+                    2 if argument < rule_ids.len() => Action::Reduce(argument),
+                    3 if argument == 0 => Action::Accept,
+                    _ => return Err(DecodeError::Invalid("action")),
+                };
+                row.insert(symbol, action);
+            }
+            actions.push(row);
+        }
+
+        let mut transitions: Transitions = HashMap::new();
+        for state in 0..states_len {
+            for _ in 0..reader.count()? {
+                let symbol = reader.below(symbols_len, "symbol")?;
+                let next_state = reader.below(states_len, "state")?;
+                transitions.insert((state, symbol), next_state);
+            }
+        }
+
+        if reader.position != bytes.len() {
+            return Err(DecodeError::Invalid("trailing bytes"));
+        }
+
+        Ok(ParseTable {
+            symbols,
+            end,
+            starts,
+            precedence,
+            policy,
+            spans,
+            labels,
+            states,
+            transitions,
+            actions,
+            rules_lhs: rule_ids.iter().map(|rule| rule.lhs).collect(),
+            rules_len: rule_ids.iter().map(|rule| rule.rhs.len()).collect(),
+            rule_ids,
+            resolved: Vec::new(),
+            defaulted: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::Mode;
+    use crate::symbols::Symbol;
+    use crate::table::ParseTable;
+    use crate::text::parse_grammar;
+
+    fn build() -> ParseTable {
+        parse_grammar("E -> E plus T | T\nT -> T times number | number")
+            .unwrap()
+            .build(Symbol::Nonterminal("E".to_string()), Mode::Lalr1)
+            .unwrap()
+    }
+
+    // The resolved and defaulted conflicts are not written, so the grammar has
+    // none for the table to read back equal
+    #[test]
+    fn bytes_are_stable_and_read_back() {
+        let table = build();
+        let bytes = table.to_bytes();
+        assert_eq!(build().to_bytes(), bytes);
+
+        let read_back = ParseTable::from_bytes(&bytes).unwrap();
+        assert!(read_back == table);
+        assert_eq!(read_back.to_bytes(), bytes);
+    }
+}
@@ -0,0 +1,959 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::{error::Error, fmt};
+
+use crate::grammar::RuleId;
+use crate::symbols::SymbolId;
+use crate::table::{Action, ParseTable};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    // The action is for a rule the table does not have
+    UnknownRule(RuleId),
+    // `$index` in the action of rule `rule`, past its rhs or `$0`
+    Placeholder { rule: usize, index: usize },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownRule(rule) => write!(f, "no rule {} -> {:?}", rule.lhs, rule.rhs),
+            Self::Placeholder { rule, index } => {
+                write!(
+                    f,
+                    "`${index}` in the action of rule {rule} is not one of its symbols"
+                )
+            }
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+impl ParseTable {
+    // Writes a self-contained module with the tables as constants, a `parse`
+    // function that returns the reduced rules in order and `parse_tree` that
+    // returns their tree, its nodes named by the rule labels
+    pub fn emit_rust(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_rust(writer, false)
+    }
+
+    // Like `emit_rust`, but the actions and gotos are in a perfect hash table
+    // keyed by state and symbol, one slot per entry and a quarter more
+    // instead of a cell for every state and symbol. A lookup hashes twice
+    // and compares one key, it never probes.
+    pub fn emit_rust_hashed(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_rust(writer, true)
+    }
+
+    fn write_rust(&self, writer: &mut impl Write, hashed: bool) -> io::Result<()> {
+        let symbols_len = self.symbols.len();
+        let states_len = self.states_len();
+        let rules_len = self.rules_len.len();
+
+        writeln!(writer, "// Generated by lrgen, do not edit")?;
+        writeln!(writer)?;
+        writeln!(writer, "pub const SYMBOLS: usize = {symbols_len};")?;
+        writeln!(writer, "pub const STATES: usize = {states_len};")?;
+        writeln!(writer, "pub const RULES: usize = {rules_len};")?;
+        writeln!(writer)?;
+
+        let names: Vec<String> = (0..symbols_len)
+            .map(|symbol| format!("{:?}", self.symbols.name(symbol)))
+            .collect();
+        writeln!(
+            writer,
+            "pub const SYMBOL_NAMES: [&str; SYMBOLS] = [{}];",
+            names.join(", ")
+        )?;
+        writeln!(writer)?;
+
+        writeln!(writer, "#[derive(Clone, Copy, Debug, PartialEq, Eq)]")?;
+        writeln!(writer, "pub enum Action {{")?;
+        writeln!(writer, "    Error,")?;
+        writeln!(writer, "    Shift(usize),")?;
+        writeln!(writer, "    Reduce(usize),")?;
+        writeln!(writer, "    Accept,")?;
+        writeln!(writer, "}}")?;
+        writeln!(writer)?;
+
+        if hashed {
+            self.write_hashed_tables(writer)?;
+        } else {
+            self.write_dense_tables(writer)?;
+        }
+
+        writeln!(
+            writer,
+            "pub const RULES_LHS: [usize; RULES] = {:?};",
+            self.rules_lhs
+        )?;
+        writeln!(
+            writer,
+            "pub const RULES_LEN: [usize; RULES] = {:?};",
+            self.rules_len
+        )?;
+        let labels: Vec<String> = (0..rules_len)
+            .map(|rule| {
+                let label = self
+                    .label(rule)
+                    .unwrap_or_else(|| self.symbols.name(self.rules_lhs[rule]));
+                format!("{label:?}")
+            })
+            .collect();
+        writeln!(
+            writer,
+            "pub const RULE_LABELS: [&str; RULES] = [{}];",
+            labels.join(", ")
+        )?;
+        writeln!(writer, "pub const END: usize = {};", self.end)?;
+        writeln!(writer)?;
+
+        writer.write_all(RUST_DRIVER.as_bytes())?;
+        writeln!(writer)?;
+        writer.write_all(RUST_TREE.as_bytes())
+    }
+
+    fn write_dense_tables(&self, writer: &mut impl Write) -> io::Result<()> {
+        let symbols_len = self.symbols.len();
+        let states_len = self.states_len();
+
+        writeln!(writer, "pub const ACTION: [[Action; SYMBOLS]; STATES] = [")?;
+        for state in 0..states_len {
+            let row: Vec<String> = (0..symbols_len)
+                .map(|symbol| match self.action(state, symbol) {
+                    Some(Action::Shift(next_state)) => format!("Action::Shift({next_state})"),
+                    Some(Action::Reduce(rule)) => format!("Action::Reduce({rule})"),
+                    Some(Action::Accept) => "Action::Accept".to_string(),
+                    Some(Action::Goto(_)) | None => "Action::Error".to_string(),
+                })
+                .collect();
+            writeln!(writer, "    [{}],", row.join(", "))?;
+        }
+        writeln!(writer, "];")?;
+        writeln!(writer)?;
+
+        writeln!(
+            writer,
+            "pub const GOTO: [[Option<usize>; SYMBOLS]; STATES] = ["
+        )?;
+        for state in 0..states_len {
+            let row: Vec<String> = (0..symbols_len)
+                .map(|symbol| match self.action(state, symbol) {
+                    Some(Action::Goto(next_state)) => format!("Some({next_state})"),
+                    _ => "None".to_string(),
+                })
+                .collect();
+            writeln!(writer, "    [{}],", row.join(", "))?;
+        }
+        writeln!(writer, "];")?;
+        writeln!(writer)?;
+
+        writer.write_all(DENSE_LOOKUP.as_bytes())?;
+        writeln!(writer)
+    }
+
+    fn write_hashed_tables(&self, writer: &mut impl Write) -> io::Result<()> {
+        let symbols_len = self.symbols.len();
+        let mut entries: Vec<(u64, String)> = Vec::new();
+        for (state, actions) in self.actions.iter().enumerate() {
+            let mut actions: Vec<(&SymbolId, &Action)> = actions.iter().collect();
+            actions.sort_by_key(|&(&symbol, _)| symbol);
+
+            for (&symbol, action) in actions {
+                let cell = (state * symbols_len + symbol) as u64 * 2;
+                let entry = match *action {
+                    Action::Shift(next_state) => (cell, format!("Action::Shift({next_state})")),
+                    Action::Reduce(rule) => (cell, format!("Action::Reduce({rule})")),
+                    Action::Accept => (cell, "Action::Accept".to_string()),
+                    Action::Goto(next_state) => (cell + 1, format!("Action::Shift({next_state})")),
+                };
+                entries.push(entry);
+            }
+        }
+
+        let keys: Vec<u64> = entries.iter().map(|(key, _)| *key).collect();
+        let (seeds, slots) = perfect_hash(&keys);
+
+        writeln!(
+            writer,
+            "pub const SEEDS: [u64; {}] = {seeds:?};",
+            seeds.len()
+        )?;
+        writeln!(
+            writer,
+            "pub const ENTRIES: [(u64, Action); {}] = [",
+            slots.len()
+        )?;
+        for slot in slots {
+            match slot {
+                Some(entry) => {
+                    writeln!(writer, "    ({}, {}),", entries[entry].0, entries[entry].1)?
+                }
+                None => writeln!(writer, "    (u64::MAX, Action::Error),")?,
+            }
+        }
+        writeln!(writer, "];")?;
+        writeln!(writer)?;
+
+        writer.write_all(HASHED_LOOKUP.as_bytes())?;
+        writeln!(writer)
+    }
+
+    // Like `emit_rust`, with an `evaluate` function as well that runs the
+    // `actions` as rules get reduced. An action is a Rust expression of type
+    // `value_type` where `$1`, `$2`... are the values of the rhs symbols, the
+    // tokens' own values for terminals. Each is moved out, so one not `Copy`
+    // can be used once, and a `$n` in a string or char literal is left alone.
+    // Rules without one take the value of their first symbol, or
+    // `Default::default()` when empty. A `$` past the
+    // rhs is an `InvalidInput` error holding a `TemplateError`, found before
+    // anything is written.
+    pub fn emit_rust_actions(
+        &self,
+        writer: &mut impl Write,
+        value_type: &str,
+        actions: &HashMap<RuleId, String>,
+    ) -> io::Result<()> {
+        let mut arms: Vec<(usize, String, Vec<bool>)> = Vec::new();
+        for (rule_id, action) in actions {
+            let rule = self
+                .rule_ids
+                .iter()
+                .position(|known| known == rule_id)
+                .ok_or_else(|| invalid(TemplateError::UnknownRule(rule_id.clone())))?;
+            let (code, used) = substitute(action, self.rules_len[rule])
+                .map_err(|index| invalid(TemplateError::Placeholder { rule, index }))?;
+            arms.push((rule, code, used));
+        }
+        arms.sort();
+
+        self.write_rust(writer, false)?;
+        writeln!(writer)?;
+        writeln!(writer, "pub type Value = {value_type};")?;
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "fn reduce(rule: usize, children: Vec<Value>) -> Value {{"
+        )?;
+        writeln!(writer, "    match rule {{")?;
+        // The children are moved out one by one, so values need not be Copy
+        for (rule, code, used) in arms {
+            writeln!(writer, "        {rule} => {{")?;
+            if !used.is_empty() {
+                let bindings: Vec<String> = used
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &used)| match used {
+                        true => format!("child_{}", index + 1),
+                        false => "_".to_string(),
+                    })
+                    .collect();
+                writeln!(
+                    writer,
+                    "            let Ok([{}]) = <[Value; {}]>::try_from(children) else {{",
+                    bindings.join(", "),
+                    used.len()
+                )?;
+                writeln!(
+                    writer,
+                    "                unreachable!(\"a reduce pops the whole rhs\");"
+                )?;
+                writeln!(writer, "            }};")?;
+            }
+            writeln!(writer, "            {code}")?;
+            writeln!(writer, "        }}")?;
+        }
+        writeln!(
+            writer,
+            "        _ => children.into_iter().next().unwrap_or_default(),"
+        )?;
+        writeln!(writer, "    }}")?;
+        writeln!(writer, "}}")?;
+        writeln!(writer)?;
+
+        writer.write_all(RUST_EVALUATE.as_bytes())
+    }
+
+    // Writes the tables as `static const` arrays for C, with both the action
+    // and the goto entries packed as described by `C_HEADER`. A table with
+    // more states or rules than 29 bits can number is an `InvalidInput`
+    // error, found before anything is written.
+    pub fn emit_c(&self, writer: &mut impl Write) -> io::Result<()> {
+        let symbols_len = self.symbols.len();
+        let states_len = self.states_len();
+        let rules_len = self.rules_len.len();
+        if states_len.max(rules_len) > C_VALUES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{states_len} states and {rules_len} rules do not fit in a C entry"),
+            ));
+        }
+
+        writer.write_all(C_HEADER.as_bytes())?;
+        writeln!(writer)?;
+        writeln!(writer, "#define LRGEN_SYMBOLS {symbols_len}")?;
+        writeln!(writer, "#define LRGEN_STATES {states_len}")?;
+        writeln!(writer, "#define LRGEN_RULES {rules_len}")?;
+        writeln!(writer, "#define LRGEN_END {}", self.end)?;
+        writeln!(writer)?;
+
+        let names: Vec<String> = (0..symbols_len)
+            .map(|symbol| c_string(self.symbols.name(symbol)))
+            .collect();
+        writeln!(
+            writer,
+            "static const char *const lrgen_symbol_names[LRGEN_SYMBOLS] = {{{}}};",
+            names.join(", ")
+        )?;
+        writeln!(writer)?;
+
+        for (array, gotos) in [("lrgen_action", false), ("lrgen_goto", true)] {
+            writeln!(
+                writer,
+                "static const uint32_t {array}[LRGEN_STATES][LRGEN_SYMBOLS] = {{"
+            )?;
+            for state in 0..states_len {
+                let row: Vec<String> = (0..symbols_len)
+                    .map(|symbol| {
+                        let entry = match self.action(state, symbol) {
+                            Some(&Action::Shift(next_state)) if !gotos => (1, next_state),
+                            Some(&Action::Reduce(rule)) if !gotos => (2, rule),
+                            Some(&Action::Goto(next_state)) if gotos => (3, next_state),
+                            Some(Action::Accept) if !gotos => (4, 0),
+                            _ => (0, 0),
+                        };
+                        (entry.1 << 3 | entry.0).to_string()
+                    })
+                    .collect();
+                writeln!(writer, "    {{{}}},", row.join(", "))?;
+            }
+            writeln!(writer, "}};")?;
+            writeln!(writer)?;
+        }
+
+        let join = |values: &[usize]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        writeln!(
+            writer,
+            "static const unsigned int lrgen_rules_lhs[LRGEN_RULES] = {{{}}};",
+            join(&self.rules_lhs)
+        )?;
+        writeln!(
+            writer,
+            "static const unsigned int lrgen_rules_len[LRGEN_RULES] = {{{}}};",
+            join(&self.rules_len)
+        )
+    }
+
+    // Writes an enum with a variant per terminal, its discriminant being the
+    // terminal id, so `Token::Number as usize` is what `parse` takes. The end
+    // marker is `End`.
+    pub fn emit_token_enum(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut variants: HashSet<String> = HashSet::new();
+
+        writeln!(writer, "#[derive(Clone, Copy, Debug, PartialEq, Eq)]")?;
+        writeln!(writer, "pub enum Token {{")?;
+        for terminal in (0..self.symbols.len()).filter(|&symbol| self.symbols.is_terminal(symbol)) {
+            let mut variant = if terminal == self.end {
+                "End".to_string()
+            } else {
+                camel_case(self.symbols.name(terminal))
+            };
+            // Names that are not identifiers, or are the keyword `Self`, get a
+            // `T` in front, clashing ones a number, the id and up until the
+            // variant is new
+            if variant.is_empty()
+                || variant.starts_with(|c: char| c.is_ascii_digit())
+                || variant == "Self"
+            {
+                variant.insert(0, 'T');
+            }
+            let name = variant.clone();
+            let mut suffix = terminal;
+            while variants.contains(&variant) {
+                variant = format!("{name}{suffix}");
+                suffix += 1;
+            }
+
+            writeln!(writer, "    {variant} = {terminal},")?;
+            variants.insert(variant);
+        }
+        writeln!(writer, "}}")
+    }
+
+    // Writes a `tokenize` function turning text into the terminal ids `parse`
+    // takes. The longest match wins and ties go to the literals, so keywords
+    // come before identifiers. Terminals with neither a literal nor a pattern
+    // are never produced.
+    pub fn emit_lexer(&self, writer: &mut impl Write) -> io::Result<()> {
+        let terminals = (0..self.symbols.len()).filter(|&symbol| self.symbols.is_terminal(symbol));
+        let literals = terminals.clone().filter_map(|terminal| {
+            let literal = self.symbols.literal(terminal)?;
+            Some((escape_pattern(literal), terminal))
+        });
+        let patterns = terminals.filter_map(|terminal| {
+            let pattern = self.symbols.pattern(terminal)?;
+            Some((pattern.to_string(), terminal))
+        });
+        let entries: Vec<String> = literals
+            .chain(patterns)
+            .map(|(pattern, terminal)| format!("    ({pattern:?}, {terminal}),"))
+            .collect();
+
+        writeln!(
+            writer,
+            "pub const LEXER_PATTERNS: [(&str, usize); {}] = [",
+            entries.len()
+        )?;
+        for entry in entries {
+            writeln!(writer, "{entry}")?;
+        }
+        writeln!(writer, "];")?;
+        writeln!(writer)?;
+
+        writer.write_all(LEXER_DRIVER.as_bytes())
+    }
+}
+
+fn invalid(error: TemplateError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+// `action` with each `$n` as `child_n`, and which of them it uses, `Err(n)`
+// for the first one that is not 1 to `len`. A `$` without digits after it is
+// left as it is, as is anything in string and char literals.
+fn substitute(action: &str, len: usize) -> Result<(String, Vec<bool>), usize> {
+    let mut code = String::new();
+    let mut used = vec![false; len];
+    let mut chars = action.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        code.push(c);
+        match c {
+            '"' => {
+                while let Some(c) = chars.next() {
+                    code.push(c);
+                    match c {
+                        '\\' => code.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            // A char literal, unless the quote starts a lifetime
+            '\'' => {
+                let mut lookahead = chars.clone();
+                let literal = match lookahead.next() {
+                    Some('\\') => true,
+                    Some(_) => lookahead.next() == Some('\''),
+                    None => false,
+                };
+                if literal {
+                    while let Some(c) = chars.next() {
+                        code.push(c);
+                        match c {
+                            '\\' => code.extend(chars.next()),
+                            '\'' => break,
+                            _ => {}
+                        }
+                    }
+                }
+                continue;
+            }
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {}
+            _ => continue,
+        }
+
+        code.pop();
+        let mut index: usize = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            index = index.saturating_mul(10).saturating_add(digit as usize);
+            chars.next();
+        }
+        if index == 0 || index > len {
+            return Err(index);
+        }
+        used[index - 1] = true;
+        code.push_str(&format!("child_{index}"));
+    }
+
+    Ok((code, used))
+}
+
+// The hash of the generated tables, `hash` in `HASHED_LOOKUP` has to be the
+// same function
+fn hash(key: u64, seed: u64) -> u64 {
+    let mut z = key ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Hash and displace: the keys go to buckets by `hash(key, 0)`, and each
+// bucket, the fullest first, takes the first seed that sends all of its keys
+// to free slots by `hash(key, seed)`. The slots hold indices into `keys`.
+fn perfect_hash(keys: &[u64]) -> (Vec<u64>, Vec<Option<usize>>) {
+    let mut seeds: Vec<u64> = vec![0; keys.len() / 4 + 1];
+    let mut slots: Vec<Option<usize>> = vec![None; keys.len() + keys.len() / 4 + 1];
+    let index = |key: u64, seed: u64, len: usize| (hash(key, seed) % len as u64) as usize;
+
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); seeds.len()];
+    for (entry, &key) in keys.iter().enumerate() {
+        buckets[index(key, 0, seeds.len())].push(entry);
+    }
+    let mut order: Vec<usize> = (0..buckets.len()).collect();
+    order.sort_by_key(|&bucket| std::cmp::Reverse(buckets[bucket].len()));
+
+    for bucket in order
+        .into_iter()
+        .take_while(|&bucket| !buckets[bucket].is_empty())
+    {
+        for seed in 1.. {
+            let mut taken: Vec<usize> = buckets[bucket]
+                .iter()
+                .map(|&entry| index(keys[entry], seed, slots.len()))
+                .collect();
+            if taken.iter().any(|&slot| slots[slot].is_some()) {
+                continue;
+            }
+            taken.sort_unstable();
+            taken.dedup();
+            if taken.len() < buckets[bucket].len() {
+                continue;
+            }
+
+            let slots_len = slots.len();
+            for &entry in &buckets[bucket] {
+                slots[index(keys[entry], seed, slots_len)] = Some(entry);
+            }
+            seeds[bucket] = seed;
+            break;
+        }
+    }
+
+    (seeds, slots)
+}
+
+// A literal as a pattern matching exactly it
+fn escape_pattern(literal: &str) -> String {
+    let mut pattern = String::new();
+    for c in literal.chars() {
+        if "\\.[]*+?".contains(c) {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+// `text` as a C string literal. Bytes outside printable ASCII are written as
+// three digit octal escapes, which never take in a digit after them, and `?`
+// is escaped so no trigraph can form.
+fn c_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'\\' => literal.push_str("\\\\"),
+            b'"' => literal.push_str("\\\""),
+            b'?' => literal.push_str("\\?"),
+            b'\n' => literal.push_str("\\n"),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{byte:03o}")),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+// `left_paren` and `left-paren` become `LeftParen`, anything that is not an
+// ASCII letter or digit separates words
+fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+// The values a C entry can hold, see `C_HEADER`
+const C_VALUES: usize = 1 << 29;
+
+const C_HEADER: &str = r#"/* Generated by lrgen, do not edit
+ *
+ * Entries of lrgen_action (terminal columns) and lrgen_goto (nonterminal
+ * columns) hold a tag in their low 3 bits and a value in the 29 above them:
+ *
+ *   LRGEN_ERROR   no action, a syntax error
+ *   LRGEN_SHIFT   shift and go to state LRGEN_VALUE(entry)
+ *   LRGEN_REDUCE  reduce by rule r = LRGEN_VALUE(entry), pop
+ *                 lrgen_rules_len[r] states and take the goto entry of the
+ *                 uncovered state on lrgen_rules_lhs[r]
+ *   LRGEN_GOTO    go to state LRGEN_VALUE(entry)
+ *   LRGEN_ACCEPT  the input is accepted
+ */
+
+#include <stdint.h>
+
+#define LRGEN_ERROR 0
+#define LRGEN_SHIFT 1
+#define LRGEN_REDUCE 2
+#define LRGEN_GOTO 3
+#define LRGEN_ACCEPT 4
+
+#define LRGEN_TAG(entry) ((entry) & 7u)
+#define LRGEN_VALUE(entry) ((entry) >> 3)
+"#;
+
+const RUST_DRIVER: &str = r#"#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub state: usize,
+    pub token: usize,
+    pub position: usize,
+}
+
+// The token at `position`, the end marker once `tokens` runs out. An end
+// marker inside the input is an error, it would accept before the rest.
+fn next_token(
+    tokens: &mut impl Iterator<Item = usize>,
+    state: usize,
+    position: usize,
+) -> Result<usize, ParseError> {
+    match tokens.next() {
+        Some(END) => Err(ParseError { state, token: END, position }),
+        Some(token) => Ok(token),
+        None => Ok(END),
+    }
+}
+
+// `tokens` are terminal ids, the end marker is appended after the last one
+pub fn parse(tokens: impl IntoIterator<Item = usize>) -> Result<Vec<usize>, ParseError> {
+    let mut tokens = tokens.into_iter();
+    let mut stack: Vec<usize> = vec![0];
+    let mut reductions: Vec<usize> = Vec::new();
+    let mut position = 0;
+    let mut token = next_token(&mut tokens, 0, position)?;
+
+    loop {
+        let state = stack[stack.len() - 1];
+        let action = action(state, token);
+
+        match action {
+            Action::Shift(next_state) => {
+                stack.push(next_state);
+                position += 1;
+                token = next_token(&mut tokens, next_state, position)?;
+            }
+            Action::Reduce(rule) => {
+                stack.truncate(stack.len() - RULES_LEN[rule]);
+                let state = stack[stack.len() - 1];
+                match goto(state, RULES_LHS[rule]) {
+                    Some(next_state) => stack.push(next_state),
+                    None => return Err(ParseError { state, token, position }),
+                }
+                reductions.push(rule);
+            }
+            Action::Accept => return Ok(reductions),
+            Action::Error => return Err(ParseError { state, token, position }),
+        }
+    }
+}
+"#;
+
+const RUST_TREE: &str = r#"#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tree {
+    Leaf(usize),
+    // The label of the reduced rule, its lhs when it has none
+    Node(&'static str, Vec<Tree>),
+}
+
+// Like `parse`, with the tree the reductions build
+pub fn parse_tree(tokens: impl IntoIterator<Item = usize>) -> Result<Tree, ParseError> {
+    let mut tokens = tokens.into_iter();
+    let mut stack: Vec<usize> = vec![0];
+    let mut trees: Vec<Tree> = Vec::new();
+    let mut position = 0;
+    let mut token = next_token(&mut tokens, 0, position)?;
+
+    loop {
+        let state = stack[stack.len() - 1];
+
+        match action(state, token) {
+            Action::Shift(next_state) => {
+                stack.push(next_state);
+                trees.push(Tree::Leaf(token));
+                position += 1;
+                token = next_token(&mut tokens, next_state, position)?;
+            }
+            Action::Reduce(rule) => {
+                let children = trees.split_off(trees.len() - RULES_LEN[rule]);
+                stack.truncate(stack.len() - RULES_LEN[rule]);
+                let state = stack[stack.len() - 1];
+                match goto(state, RULES_LHS[rule]) {
+                    Some(next_state) => stack.push(next_state),
+                    None => return Err(ParseError { state, token, position }),
+                }
+                trees.push(Tree::Node(RULE_LABELS[rule], children));
+            }
+            Action::Accept => return Ok(trees.pop().expect("accepted without a tree")),
+            Action::Error => return Err(ParseError { state, token, position }),
+        }
+    }
+}
+"#;
+
+const RUST_EVALUATE: &str = r#"// `tokens` are terminal ids with their values, the end marker is appended
+// after the last one
+pub fn evaluate(tokens: impl IntoIterator<Item = (usize, Value)>) -> Result<Value, ParseError> {
+    let mut tokens = tokens.into_iter();
+    let mut stack: Vec<usize> = vec![0];
+    let mut values: Vec<Value> = Vec::new();
+    let mut position = 0;
+    let mut next = tokens.next();
+
+    loop {
+        let state = stack[stack.len() - 1];
+        let token = match next {
+            // Like `next_token`, the end marker only comes after the input
+            Some((END, _)) => return Err(ParseError { state, token: END, position }),
+            Some((token, _)) => token,
+            None => END,
+        };
+
+        match action(state, token) {
+            Action::Shift(next_state) => {
+                stack.push(next_state);
+                values.push(next.take().expect("the end marker is never shifted").1);
+                next = tokens.next();
+                position += 1;
+            }
+            Action::Reduce(rule) => {
+                let children = values.split_off(values.len() - RULES_LEN[rule]);
+                stack.truncate(stack.len() - RULES_LEN[rule]);
+                let state = stack[stack.len() - 1];
+                match goto(state, RULES_LHS[rule]) {
+                    Some(next_state) => stack.push(next_state),
+                    None => return Err(ParseError { state, token, position }),
+                }
+                values.push(reduce(rule, children));
+            }
+            Action::Accept => return Ok(values.pop().expect("accepted without a value")),
+            Action::Error => return Err(ParseError { state, token, position }),
+        }
+    }
+}
+"#;
+
+const DENSE_LOOKUP: &str = r#"pub fn action(state: usize, symbol: usize) -> Action {
+    ACTION[state].get(symbol).copied().unwrap_or(Action::Error)
+}
+
+pub fn goto(state: usize, nonterminal: usize) -> Option<usize> {
+    GOTO[state][nonterminal]
+}
+"#;
+
+const HASHED_LOOKUP: &str = r#"// The action of a state on a symbol is the entry keyed by
+// 2 * (state * SYMBOLS + symbol), its goto the one keyed by that plus 1, which
+// holds the next state as a shift. `SEEDS` picks the slot of each key.
+fn hash(key: u64, seed: u64) -> u64 {
+    let mut z = key ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn entry(state: usize, symbol: usize, goto: bool) -> Action {
+    if state >= STATES || symbol >= SYMBOLS {
+        return Action::Error;
+    }
+    let key = (state * SYMBOLS + symbol) as u64 * 2 + goto as u64;
+    let seed = SEEDS[(hash(key, 0) % SEEDS.len() as u64) as usize];
+    let (found, action) = ENTRIES[(hash(key, seed) % ENTRIES.len() as u64) as usize];
+    if found == key { action } else { Action::Error }
+}
+
+pub fn action(state: usize, symbol: usize) -> Action {
+    entry(state, symbol, false)
+}
+
+pub fn goto(state: usize, nonterminal: usize) -> Option<usize> {
+    match entry(state, nonterminal, true) {
+        Action::Shift(next_state) => Some(next_state),
+        _ => None,
+    }
+}
+"#;
+
+const LEXER_DRIVER: &str = r#"#[derive(Debug, PartialEq, Eq)]
+pub struct LexError {
+    // Index of the first character no pattern matches
+    pub position: usize,
+}
+
+// Whitespace between tokens is skipped
+pub fn tokenize(input: &str) -> Result<Vec<usize>, LexError> {
+    let input: Vec<char> = input.chars().collect();
+    let patterns: Vec<(Vec<char>, usize)> = LEXER_PATTERNS
+        .iter()
+        .map(|&(pattern, terminal)| (pattern.chars().collect(), terminal))
+        .collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < input.len() {
+        if input[position].is_whitespace() {
+            position += 1;
+            continue;
+        }
+
+        let mut longest: Option<(usize, usize)> = None;
+        for (pattern, terminal) in &patterns {
+            let length = longest_match(pattern, &input[position..]).unwrap_or(0);
+            if length > longest.map_or(0, |(longest, _)| longest) {
+                longest = Some((length, *terminal));
+            }
+        }
+
+        match longest {
+            Some((length, terminal)) => {
+                tokens.push(terminal);
+                position += length;
+            }
+            None => return Err(LexError { position }),
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Patterns are sequences of characters, `.`, classes like `[a-z_]` or
+// `[^0-9]` and the escapes `\d`, `\w` and `\s`, each optionally followed by
+// `*`, `+` or `?`. A backslash before any other character matches it as is.
+fn longest_match(pattern: &[char], input: &[char]) -> Option<usize> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    let atom_len = match pattern {
+        ['\\', _, ..] => 2,
+        ['[', rest @ ..] => match rest.iter().skip(1).position(|&c| c == ']') {
+            Some(close) => close + 3,
+            None => 1,
+        },
+        _ => 1,
+    };
+    let (atom, rest) = pattern.split_at(atom_len);
+    let (min, max, rest) = match rest.first() {
+        Some('*') => (0, usize::MAX, &rest[1..]),
+        Some('+') => (1, usize::MAX, &rest[1..]),
+        Some('?') => (0, 1, &rest[1..]),
+        _ => (1, 1, rest),
+    };
+
+    let mut count = 0;
+    while count < max && count < input.len() && atom_matches(atom, input[count]) {
+        count += 1;
+    }
+
+    (min..=count)
+        .rev()
+        .filter_map(|taken| longest_match(rest, &input[taken..]).map(|length| taken + length))
+        .max()
+}
+
+fn atom_matches(atom: &[char], c: char) -> bool {
+    match atom {
+        ['.'] => true,
+        ['\\', 'd'] => c.is_ascii_digit(),
+        ['\\', 'w'] => c.is_alphanumeric() || c == '_',
+        ['\\', 's'] => c.is_whitespace(),
+        ['\\', escaped] => c == *escaped,
+        ['[', class @ .., ']'] => {
+            let (negated, class) = match class {
+                ['^', class @ ..] => (true, class),
+                _ => (false, class),
+            };
+            let mut matched = false;
+            let mut index = 0;
+            while index < class.len() {
+                if index + 2 < class.len() && class[index + 1] == '-' {
+                    matched |= class[index] <= c && c <= class[index + 2];
+                    index += 3;
+                } else {
+                    matched |= class[index] == c;
+                    index += 1;
+                }
+            }
+            matched != negated
+        }
+        [single] => c == *single,
+        _ => false,
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{c_string, substitute};
+    use crate::grammar::Mode;
+    use crate::symbols::Symbol;
+    use crate::text::parse_grammar;
+
+    #[test]
+    fn substitute_binds_children_outside_literals() {
+        let (code, used) =
+            substitute(r#"format!("$1 {}", $3) + &'$'.to_string() + $1"#, 3).unwrap();
+        assert_eq!(
+            code,
+            r#"format!("$1 {}", child_3) + &'$'.to_string() + child_1"#
+        );
+        assert_eq!(used, [true, false, true]);
+
+        let (code, _) = substitute("{ let r: &'static str = \"\\\"$1\"; $1 }", 1).unwrap();
+        assert_eq!(code, "{ let r: &'static str = \"\\\"$1\"; child_1 }");
+
+        assert_eq!(substitute("$2", 1), Err(2));
+        assert_eq!(substitute("$0", 1), Err(0));
+    }
+
+    #[test]
+    fn token_variants_are_unique() {
+        let mut grammar = parse_grammar("S -> 'a-b' 'a b4' 'a.b' a_b2 a_b").unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+        let mut enum_text = Vec::new();
+        table.emit_token_enum(&mut enum_text).unwrap();
+
+        let variants: Vec<String> = String::from_utf8(enum_text)
+            .unwrap()
+            .lines()
+            .filter_map(|line| Some(line.trim().split_once(" = ")?.0.to_string()))
+            .collect();
+        let unique: HashSet<&String> = variants.iter().collect();
+        assert_eq!(variants.len(), 6, "{variants:?}");
+        assert_eq!(unique.len(), variants.len(), "{variants:?}");
+    }
+
+    #[test]
+    fn c_string_escapes() {
+        assert_eq!(c_string("plus"), r#""plus""#);
+        assert_eq!(c_string("a\\\"b\n"), r#""a\\\"b\n""#);
+        assert_eq!(c_string("??=\t1"), r#""\?\?=\0111""#);
+        assert_eq!(c_string("é"), r#""\303\251""#);
+    }
+}
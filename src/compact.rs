@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use crate::symbols::SymbolId;
+use crate::table::{Action, ParseTable};
+
+// Compressed action table
+//
+// `rows[state]` is an index into `entries`, states whose actions are identical
+// share the same row. Each row holds its `(symbol, action)` pairs sorted by
+// symbol id, except for the reduces by its most common rule, which is the
+// default of the row instead, like yacc's `yydefact`. A terminal missing from
+// a row reduces by the default, or else is a syntax error in that state, and a
+// missing nonterminal has no goto. The default also fills the error cells of
+// the terminals, so it postpones finding an error: it is only found after
+// reducing, before anything else is shifted. `action_exact` keeps them.
+pub struct CompactTable {
+    pub(crate) rows: Vec<usize>,
+    pub(crate) entries: Vec<Vec<(SymbolId, Action)>>,
+    // By row, always a reduce
+    pub(crate) defaults: Vec<Option<Action>>,
+    // By row, the terminals the default stands for
+    pub(crate) defaulted: Vec<Vec<SymbolId>>,
+    // By symbol id, the columns the defaults apply to
+    pub(crate) terminals: Vec<bool>,
+    pub(crate) rules_lhs: Vec<usize>,
+    pub(crate) rules_len: Vec<usize>,
+    pub(crate) end: SymbolId,
+}
+
+impl CompactTable {
+    pub fn rows(&self) -> &[usize] {
+        &self.rows
+    }
+
+    pub fn entries(&self) -> &[Vec<(SymbolId, Action)>] {
+        &self.entries
+    }
+
+    // The rule `state` reduces by on the symbols its row leaves out
+    pub fn default_reduce(&self, state: usize) -> Option<usize> {
+        match self.defaults[*self.rows.get(state)?] {
+            Some(Action::Reduce(rule)) => Some(rule),
+            _ => None,
+        }
+    }
+
+    // The action of the row, or the default reduce on a terminal it leaves
+    // out, which may be an error cell of the full table
+    pub fn action(&self, state: usize, symbol: SymbolId) -> Option<&Action> {
+        let row_index = *self.rows.get(state)?;
+        let row = &self.entries[row_index];
+        match row.binary_search_by_key(&symbol, |&(symbol, _)| symbol) {
+            Ok(index) => Some(&row[index].1),
+            Err(_) if self.terminals.get(symbol) == Some(&true) => {
+                self.defaults[row_index].as_ref()
+            }
+            Err(_) => None,
+        }
+    }
+
+    // Gotos are never defaulted, a nonterminal without one in the row has none
+    pub fn goto(&self, state: usize, nonterminal: SymbolId) -> Option<usize> {
+        match self.action(state, nonterminal)? {
+            &Action::Goto(next_state) => Some(next_state),
+            _ => None,
+        }
+    }
+
+    // The action of the full table, `None` for its error cells
+    pub fn action_exact(&self, state: usize, symbol: SymbolId) -> Option<&Action> {
+        let row_index = *self.rows.get(state)?;
+        let row = &self.entries[row_index];
+        match row.binary_search_by_key(&symbol, |&(symbol, _)| symbol) {
+            Ok(index) => Some(&row[index].1),
+            Err(_) if self.defaulted[row_index].binary_search(&symbol).is_ok() => {
+                self.defaults[row_index].as_ref()
+            }
+            Err(_) => None,
+        }
+    }
+
+    // Whether the first start symbol derives `tokens`, like
+    // `ParseTable::recognizes` but going by the defaults, the error being the
+    // position of the token it is found on. The defaults only reduce, so that
+    // is the token the full table finds it on as well.
+    pub fn recognize(&self, tokens: &[SymbolId]) -> Result<(), usize> {
+        if let Some(position) = tokens.iter().position(|&token| token == self.end) {
+            return Err(position);
+        }
+
+        let mut states: Vec<usize> = Vec::from([0]);
+        let mut position = 0;
+        loop {
+            let state = states[states.len() - 1];
+            let token = tokens.get(position).copied().unwrap_or(self.end);
+            match self.action(state, token) {
+                Some(&Action::Shift(next_state)) => {
+                    states.push(next_state);
+                    position += 1;
+                }
+                Some(&Action::Reduce(rule)) => {
+                    states.truncate(states.len() - self.rules_len[rule]);
+                    match self.goto(states[states.len() - 1], self.rules_lhs[rule]) {
+                        Some(next_state) => states.push(next_state),
+                        None => return Err(position),
+                    }
+                }
+                Some(Action::Accept) => return Ok(()),
+                Some(Action::Goto(_)) | None => return Err(position),
+            }
+        }
+    }
+}
+
+impl ParseTable {
+    pub fn compact(&self) -> CompactTable {
+        let mut rows: Vec<usize> = Vec::new();
+        let mut entries: Vec<Vec<(SymbolId, Action)>> = Vec::new();
+        let mut defaults: Vec<Option<Action>> = Vec::new();
+        let mut defaulted: Vec<Vec<SymbolId>> = Vec::new();
+        let mut index: HashMap<Vec<(SymbolId, Action)>, usize> = HashMap::new();
+
+        for actions in &self.actions {
+            let mut row: Vec<(SymbolId, Action)> = actions
+                .iter()
+                .map(|(&symbol, action)| (symbol, action.clone()))
+                .collect();
+            row.sort_by_key(|&(symbol, _)| symbol);
+
+            let row_index = match index.get(&row) {
+                Some(&row_index) => row_index,
+                None => {
+                    let row_index = entries.len();
+                    index.insert(row.clone(), row_index);
+                    let default = default_reduce(&row);
+                    let (by_default, kept): (Vec<_>, Vec<_>) =
+                        row.into_iter().partition(|(_, action)| {
+                            default.is_some_and(|rule| *action == Action::Reduce(rule))
+                        });
+                    entries.push(kept);
+                    defaults.push(default.map(Action::Reduce));
+                    defaulted.push(by_default.into_iter().map(|(symbol, _)| symbol).collect());
+                    row_index
+                }
+            };
+
+            rows.push(row_index);
+        }
+
+        CompactTable {
+            rows,
+            entries,
+            defaults,
+            defaulted,
+            terminals: (0..self.symbols.len())
+                .map(|symbol| self.symbols.is_terminal(symbol))
+                .collect(),
+            rules_lhs: self.rules_lhs.clone(),
+            rules_len: self.rules_len.clone(),
+            end: self.end,
+        }
+    }
+}
+
+// The rule most of the reduces of `row` are by, the lowest numbered on a tie
+fn default_reduce(row: &[(SymbolId, Action)]) -> Option<usize> {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for (_, action) in row {
+        if let Action::Reduce(rule) = *action {
+            *counts.entry(rule).or_default() += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|&(rule, count)| (count, std::cmp::Reverse(rule)))
+        .map(|(rule, _)| rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::Mode;
+    use crate::parser::Parser;
+    use crate::symbols::Symbol;
+    use crate::table::Action;
+    use crate::text::parse_grammar;
+
+    #[test]
+    fn compact_table_decides_like_the_full_table() {
+        let mut grammar =
+            parse_grammar("E -> E plus T | T\nT -> T times F | F\nF -> lp E rp | number\n")
+                .unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("E".to_string()), Mode::Lalr1)
+            .unwrap();
+        let compact = table.compact();
+
+        for state in 0..table.states_len() {
+            for symbol in 0..table.symbols().len() {
+                let full = table.action(state, symbol);
+                assert_eq!(compact.action_exact(state, symbol), full);
+
+                assert_eq!(compact.goto(state, symbol), table.goto(state, symbol));
+
+                let found = compact.action(state, symbol);
+                if table.symbols().is_nonterminal(symbol) || full.is_some() {
+                    assert_eq!(found, full, "state {state}, symbol {symbol}");
+                } else {
+                    let default = compact.default_reduce(state).map(Action::Reduce);
+                    assert_eq!(found, default.as_ref(), "state {state}, symbol {symbol}");
+                }
+            }
+        }
+    }
+
+    // Every input of up to 5 tokens is accepted by one exactly when by the
+    // other, and rejected on the same token
+    #[test]
+    fn compact_table_parses_like_the_full_table() {
+        let mut grammar =
+            parse_grammar("E -> E plus T | T\nT -> T times F | F\nF -> lp E rp | number\n")
+                .unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("E".to_string()), Mode::Lalr1)
+            .unwrap();
+        let compact = table.compact();
+        let terminals: Vec<usize> = ["number", "plus", "times", "lp", "rp"]
+            .iter()
+            .map(|name| table.symbols().id_by_name(name).unwrap())
+            .collect();
+
+        let mut inputs: Vec<Vec<usize>> = Vec::from([Vec::new()]);
+        let mut accepted = 0;
+        for _ in 0..=5 {
+            let mut longer = Vec::new();
+            for input in &inputs {
+                let full = Parser::new(&table)
+                    .parse(input)
+                    .map(|_| ())
+                    .map_err(|error| error.position);
+                assert_eq!(compact.recognize(input), full, "{input:?}");
+                accepted += usize::from(full.is_ok());
+
+                for &terminal in &terminals {
+                    let mut input = input.clone();
+                    input.push(terminal);
+                    longer.push(input);
+                }
+            }
+            inputs = longer;
+        }
+        assert!(accepted > 10);
+
+        let end = table.symbols().end().unwrap();
+        assert_eq!(
+            compact.recognize(&[terminals[0], end, terminals[1]]),
+            Err(1)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeSet;
+
+use crate::grammar::Grammar;
+use crate::symbols::{Symbol, SymbolId};
+
+// A rule spelled with symbols, so it means the same in any grammar
+pub type NamedRule = (Symbol, Vec<Symbol>);
+
+// What turns one grammar into another, everything sorted
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GrammarDiff {
+    pub added_rules: Vec<NamedRule>,
+    pub removed_rules: Vec<NamedRule>,
+    pub added_symbols: Vec<Symbol>,
+    pub removed_symbols: Vec<Symbol>,
+}
+
+impl GrammarDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == GrammarDiff::default()
+    }
+}
+
+impl Grammar {
+    // The S' rules of earlier builds are left out
+    fn named_rules(&self) -> BTreeSet<NamedRule> {
+        let symbol = |id: SymbolId| self.symbols.collection[id].clone();
+
+        self.rules
+            .iter()
+            .filter(|&(&lhs, _)| !self.is_augmented(lhs))
+            .flat_map(|(&lhs, rules)| {
+                rules
+                    .iter()
+                    .map(move |rhs| (symbol(lhs), rhs.iter().map(|&id| symbol(id)).collect()))
+            })
+            .collect()
+    }
+
+    fn named_symbols(&self) -> BTreeSet<Symbol> {
+        (0..self.symbols.len())
+            .filter(|&symbol| !self.is_augmented(symbol) && !self.symbols.is_end(symbol))
+            .map(|symbol| self.symbols.collection[symbol].clone())
+            .collect()
+    }
+
+    // Symbols are matched by name, their ids in each grammar do not matter
+    pub fn diff(&self, other: &Grammar) -> GrammarDiff {
+        let (rules, other_rules) = (self.named_rules(), other.named_rules());
+        let (symbols, other_symbols) = (self.named_symbols(), other.named_symbols());
+
+        GrammarDiff {
+            added_rules: other_rules.difference(&rules).cloned().collect(),
+            removed_rules: rules.difference(&other_rules).cloned().collect(),
+            added_symbols: other_symbols.difference(&symbols).cloned().collect(),
+            removed_symbols: symbols.difference(&other_symbols).cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+
+use crate::item::Item;
+use crate::table::{Action, ParseTable};
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl ParseTable {
+    pub(crate) fn is_kernel(&self, item: &Item) -> bool {
+        item.position > 0 || self.starts.iter().any(|&(_, start)| item.rule.lhs == start)
+    }
+
+    // The items of `state` that are not there by closure, the ones past some
+    // symbol and the S' items. Empty for a state past the last one.
+    pub fn kernel(&self, state: usize) -> BTreeSet<Item> {
+        self.states
+            .get(state)
+            .into_iter()
+            .flat_map(|set| set.iter())
+            .filter(|item| self.is_kernel(item))
+            .cloned()
+            .collect()
+    }
+
+    // Reduce states are filled and accept states drawn with a double border,
+    // shifts a settled conflict took out of the table are dashed
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph lr {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for (state, set) in self.states.iter().enumerate() {
+            let mut label = format!("{state}\\l");
+            for item in set.iter().filter(|item| self.is_kernel(item)) {
+                label.push_str(&escape(&item.format(&self.symbols)));
+                label.push_str("\\l");
+            }
+
+            let actions = &self.actions[state];
+            let mut attributes = String::new();
+            if actions
+                .values()
+                .any(|action| matches!(action, Action::Accept))
+            {
+                attributes.push_str(", peripheries=2");
+            }
+            if actions
+                .values()
+                .any(|action| matches!(action, Action::Reduce(..)))
+            {
+                attributes.push_str(", style=filled, fillcolor=lightgrey");
+            }
+
+            dot.push_str(&format!("    {state} [label=\"{label}\"{attributes}];\n"));
+        }
+
+        let mut transitions: Vec<_> = self.transitions.iter().collect();
+        transitions.sort();
+        for (&(state, symbol), &next_state) in transitions {
+            let name = escape(&self.symbols.display_name(symbol));
+            let style = match self.actions[state].get(&symbol) {
+                Some(&Action::Shift(..) | &Action::Goto(..)) => "",
+                _ => ", style=dashed",
+            };
+            dot.push_str(&format!(
+                "    {state} -> {next_state} [label=\"{name}\"{style}];\n"
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::Mode;
+    use crate::symbols::Symbol;
+    use crate::text::parse_grammar;
+
+    #[test]
+    fn one_node_per_state_and_labelled_edges() {
+        let table = parse_grammar("S -> a b")
+            .unwrap()
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+        let dot = table.to_dot();
+
+        assert!(dot.starts_with("digraph lr {\n"));
+        assert!(dot.ends_with("}\n"));
+        let nodes = dot
+            .lines()
+            .filter_map(|line| line.trim_start().split_once(" [label="))
+            .filter(|(node, _)| node.parse::<usize>().is_ok())
+            .count();
+        assert_eq!(nodes, table.states_len());
+        // The goto on `S` and the shifts of `a` and `b`
+        assert!(dot.contains("    0 -> 1 [label=\"S\"];\n"));
+        assert!(dot.contains("    0 -> 2 [label=\"a\"];\n"));
+        assert!(dot.contains("    2 -> 3 [label=\"b\"];\n"));
+        assert!(dot.contains("1\\l[S' -> S ·, $]\\l\", peripheries=2"));
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use crate::grammar::{Grammar, RuleId};
+use crate::symbols::{Symbol, SymbolId};
+
+// splitmix64, enough to pick rules and small enough to not need a crate
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, limit: usize) -> usize {
+        (self.next() % limit as u64) as usize
+    }
+}
+
+// One of `choices` by weight, any of them alike when all weigh 0
+fn pick<'a>(choices: &[(&'a Vec<SymbolId>, u64)], random: &mut Random) -> &'a Vec<SymbolId> {
+    let total: u64 = choices.iter().map(|&(_, weight)| weight).sum();
+    if total == 0 {
+        return choices[random.below(choices.len())].0;
+    }
+
+    let mut target = random.next() % total;
+    for &(rhs, weight) in choices {
+        if target < weight {
+            return rhs;
+        }
+        target -= weight;
+    }
+    unreachable!("the weights add up to the total")
+}
+
+impl Grammar {
+    // How deep the shallowest derivation tree of each nonterminal is, a rule
+    // made only of terminals being 1. Nonterminals that derive no string are
+    // left out.
+    fn heights(&self) -> HashMap<SymbolId, usize> {
+        let mut heights: HashMap<SymbolId, usize> = HashMap::new();
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (&lhs, rules) in &self.rules {
+                let height = rules
+                    .iter()
+                    .filter_map(|rhs| self.rule_height(rhs, &heights))
+                    .min();
+                if let Some(height) = height
+                    && heights.get(&lhs).is_none_or(|&known| height < known)
+                {
+                    heights.insert(lhs, height);
+                    changed = true;
+                }
+            }
+        }
+
+        heights
+    }
+
+    fn rule_height(&self, rhs: &[SymbolId], heights: &HashMap<SymbolId, usize>) -> Option<usize> {
+        rhs.iter()
+            .map(|&symbol| match self.symbols.is_terminal(symbol) {
+                true => Some(0),
+                false => heights.get(&symbol).copied(),
+            })
+            .try_fold(0, |height, symbol| Some(height.max(symbol?)))
+            .map(|height| height + 1)
+    }
+
+    fn weight(&self, lhs: SymbolId, rhs: &[SymbolId]) -> u64 {
+        let rule = RuleId {
+            lhs,
+            rhs: rhs.to_vec(),
+        };
+        self.weights
+            .get(&rule)
+            .map_or(1, |&weight| u64::from(weight))
+    }
+
+    // A random sentence of `start`, from a derivation tree at most `max_depth`
+    // deep. Each nonterminal takes one of its rules that still fits in the
+    // depth left, as often as its weight says, and the same `seed` always
+    // gives the same sentence. `None` when no derivation of `start` is that
+    // shallow.
+    pub fn generate_sentence(
+        &self,
+        start: &Symbol,
+        max_depth: usize,
+        seed: u64,
+    ) -> Option<Vec<SymbolId>> {
+        let start = self.symbols.id(start)?;
+        let heights = self.heights();
+        if self.symbols.is_nonterminal(start) && heights.get(&start)? > &max_depth {
+            return None;
+        }
+
+        let mut random = Random(seed);
+        let mut sentence: Vec<SymbolId> = Vec::new();
+        // Symbols still to expand, last first, with the depth left for them
+        let mut to_expand: Vec<(SymbolId, usize)> = Vec::from([(start, max_depth)]);
+
+        while let Some((symbol, depth)) = to_expand.pop() {
+            if self.symbols.is_terminal(symbol) {
+                sentence.push(symbol);
+                continue;
+            }
+
+            let fitting: Vec<(&Vec<SymbolId>, u64)> = self.rules[&symbol]
+                .iter()
+                .filter(|rhs| {
+                    self.rule_height(rhs, &heights)
+                        .is_some_and(|height| height <= depth)
+                })
+                .map(|rhs| (rhs, self.weight(symbol, rhs)))
+                .collect();
+            let rhs = pick(&fitting, &mut random);
+            to_expand.extend(rhs.iter().rev().map(|&symbol| (symbol, depth - 1)));
+        }
+
+        Some(sentence)
+    }
+}
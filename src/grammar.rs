@@ -0,0 +1,1293 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::automaton::{ItemGraph, Transitions, build_actions, merge_compatible, merge_cores};
+use crate::item::{Item, get_new_states, union_lookaheads};
+use crate::precedence::{Associativity, ConflictPolicy, Precedence};
+use crate::symbols::{EPSILON, RenameError, Symbol, SymbolId, Symbols};
+use crate::table::{Conflict, GrammarError, ParseTable};
+
+type Rhs = Vec<SymbolId>;
+
+// Where a rule was defined, for diagnostics
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Span {
+    // Position of the lhs in the text of `parse_grammar`
+    Text { line: usize, column: usize },
+    // Order of the `add_rule` call, the default
+    Index(usize),
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text { line, column } => write!(f, "line {line}, column {column}"),
+            Self::Index(index) => write!(f, "index {index}"),
+        }
+    }
+}
+
+pub struct Rule {
+    lhs: Symbol,
+    rhs: Vec<Symbol>,
+    precedence: Option<Symbol>,
+    span: Option<Span>,
+    weight: Option<u32>,
+    label: Option<String>,
+}
+
+impl Rule {
+    pub fn new(lhs: Symbol, rhs: Symbol) -> Self {
+        Rule {
+            lhs,
+            rhs: Vec::from([rhs]),
+            precedence: None,
+            span: None,
+            weight: None,
+            label: None,
+        }
+    }
+
+    // A -> ε
+    pub fn empty(lhs: Symbol) -> Self {
+        Rule {
+            lhs,
+            rhs: Vec::new(),
+            precedence: None,
+            span: None,
+            weight: None,
+            label: None,
+        }
+    }
+
+    pub fn rhs(mut self, rhs: Symbol) -> Self {
+        self.rhs.push(rhs);
+        self
+    }
+
+    // Takes the precedence of `terminal` instead of the one of its last terminal
+    pub fn precedence(mut self, terminal: Symbol) -> Self {
+        self.precedence = Some(terminal);
+        self
+    }
+
+    pub fn span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    // How often `Grammar::generate_sentence` picks the rule relative to the
+    // other rules of its lhs, 1 when not given
+    pub fn weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    // The name of the node the rule builds in the trees of emitted parsers,
+    // `E -> E plus T` building an `Add` rather than an `E`
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+}
+
+#[derive(Hash, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct RuleId {
+    pub lhs: SymbolId,
+    pub rhs: Vec<SymbolId>,
+}
+
+impl RuleId {
+    pub fn format(&self, symbols: &Symbols) -> String {
+        let mut text = format!("{} ->", symbols.display_name(self.lhs));
+        if self.rhs.is_empty() {
+            text.push_str(&format!(" {}", symbols.name(EPSILON)));
+        }
+        for &rhs in &self.rhs {
+            text.push_str(&format!(" {}", symbols.display_name(rhs)));
+        }
+        text
+    }
+
+    pub fn render(&self, symbols: &Symbols) {
+        println!("{}", self.format(symbols));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Lr0,
+    Slr1,
+    Lalr1,
+    // LALR(1) merging only where it brings no new conflict, see
+    // `merge_compatible`
+    MinimalLr1,
+    Lr1,
+}
+
+pub struct Grammar {
+    pub(crate) symbols: Symbols,
+    pub(crate) end: SymbolId,
+    pub(crate) rules: HashMap<usize, Vec<Rhs>>,
+    pub(crate) rules_lhs: Vec<usize>,
+    pub(crate) rules_len: Vec<usize>,
+    // Every rule by number, in the order they were added
+    pub(crate) rule_ids: Vec<RuleId>,
+    pub(crate) precedence: Precedence,
+    pub(crate) spans: HashMap<RuleId, Span>,
+    // Only the rules given a weight
+    pub(crate) weights: HashMap<RuleId, u32>,
+    // Only the rules given a label
+    pub(crate) labels: HashMap<RuleId, String>,
+    start: Option<Symbol>,
+    kernels_only: bool,
+    max_states: Option<usize>,
+    policy: ConflictPolicy,
+    // Start symbol to its augmented S', so building again reuses the rule
+    augmented: HashMap<SymbolId, SymbolId>,
+}
+
+impl Grammar {
+    pub fn new() -> Self {
+        let mut symbols = Symbols::new();
+        let end = symbols.add_end();
+
+        Grammar {
+            symbols,
+            end,
+            rules: HashMap::new(),
+            rules_lhs: Vec::new(),
+            rules_len: Vec::new(),
+            rule_ids: Vec::new(),
+            precedence: Precedence::default(),
+            spans: HashMap::new(),
+            weights: HashMap::new(),
+            labels: HashMap::new(),
+            start: None,
+            kernels_only: false,
+            max_states: None,
+            policy: ConflictPolicy::default(),
+            augmented: HashMap::new(),
+        }
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) -> RuleId {
+        let lhs = self.symbols.add_symbol(rule.lhs);
+        let rhs: Vec<SymbolId> = rule
+            .rhs
+            .into_iter()
+            .map(|rhs| self.symbols.add_symbol(rhs))
+            .collect();
+
+        // The same rule again is the one already added, it only gets its
+        // precedence updated
+        let rules = self.rules.entry(lhs).or_default();
+        let duplicate = rules.contains(&rhs);
+        if !duplicate {
+            rules.push(rhs.clone());
+            self.spans.insert(
+                RuleId {
+                    lhs,
+                    rhs: rhs.clone(),
+                },
+                rule.span.unwrap_or(Span::Index(self.rules_lhs.len())),
+            );
+            self.rules_lhs.push(lhs);
+            self.rules_len.push(rhs.len());
+            self.rule_ids.push(RuleId {
+                lhs,
+                rhs: rhs.clone(),
+            });
+        }
+
+        let rule_id = RuleId { lhs, rhs };
+        if let Some(terminal) = rule.precedence {
+            let terminal = self.symbols.add_symbol(terminal);
+            self.precedence.rules.insert(rule_id.clone(), terminal);
+        }
+        if let Some(weight) = rule.weight {
+            self.weights.insert(rule_id.clone(), weight);
+        }
+        if let Some(label) = rule.label {
+            self.labels.insert(rule_id.clone(), label);
+        }
+
+        rule_id
+    }
+
+    // One rule per rhs, like a line `lhs -> a | b c` of the text format
+    pub fn add_alternatives(&mut self, lhs: Symbol, alternatives: Vec<Vec<Symbol>>) -> Vec<RuleId> {
+        alternatives
+            .into_iter()
+            .map(|rhs| {
+                let rule = rhs
+                    .into_iter()
+                    .fold(Rule::empty(lhs.clone()), |rule, symbol| rule.rhs(symbol));
+                self.add_rule(rule)
+            })
+            .collect()
+    }
+
+    // Adds the rules of `other` after these, its symbols being the ones of
+    // this grammar with the same kind and name. Rules of a nonterminal both
+    // define are alternatives of it, the precedence levels of `other` go
+    // above these and its start is only taken when this grammar has none.
+    pub fn extend(&mut self, other: Grammar) {
+        let symbol = |id: SymbolId| other.symbols.collection[id].clone();
+
+        for rule_id in &other.rule_ids {
+            if other.is_augmented(rule_id.lhs) {
+                continue;
+            }
+
+            let mut rule = rule_id
+                .rhs
+                .iter()
+                .fold(Rule::empty(symbol(rule_id.lhs)), |rule, &rhs| {
+                    rule.rhs(symbol(rhs))
+                });
+            if let Some(&terminal) = other.precedence.rules.get(rule_id) {
+                rule = rule.precedence(symbol(terminal));
+            }
+            // A position in the other text still tells where the rule is, an
+            // index in the other grammar would not
+            if let Some(&span @ Span::Text { .. }) = other.spans.get(rule_id) {
+                rule = rule.span(span);
+            }
+            if let Some(&weight) = other.weights.get(rule_id) {
+                rule = rule.weight(weight);
+            }
+            if let Some(label) = other.labels.get(rule_id) {
+                rule = rule.label(label);
+            }
+            self.add_rule(rule);
+        }
+
+        for (associativity, terminals) in other.precedence.levels() {
+            let terminals: Vec<Symbol> = terminals.into_iter().map(symbol).collect();
+            self.precedence(associativity, &terminals);
+        }
+
+        for id in 0..other.symbols.len() {
+            if other.symbols.is_end(id) {
+                continue;
+            }
+            if let Some(literal) = other.symbols.literal(id) {
+                self.literal(symbol(id), literal);
+            }
+            if let Some(pattern) = other.symbols.pattern(id) {
+                self.pattern(symbol(id), pattern);
+            }
+            if let Some(code) = other.symbols.code(id) {
+                self.token_code(symbol(id), code);
+            }
+        }
+
+        if self.start.is_none() {
+            self.start = other.start.clone();
+        }
+    }
+
+    // The same language without unit rules `A -> B`, A taking the other rules
+    // of every nonterminal it reaches through them instead, chains and cycles
+    // of them included. Inlined rules keep their precedence, span, weight and
+    // label.
+    // The symbols keep their ids and the options are carried over. Parsing
+    // does no unit reductions then, though the copied rules can take more
+    // states than the unit rules did. Nonterminals only used in unit rules
+    // are left unreachable.
+    pub fn without_unit_rules(&self) -> Grammar {
+        let is_unit = |rhs: &Rhs| rhs.len() == 1 && self.symbols.is_nonterminal(rhs[0]);
+        let mut precedence = self.precedence.clone();
+        precedence.rules.clear();
+        let mut grammar = Grammar {
+            symbols: self.symbols.clone(),
+            end: self.end,
+            precedence,
+            start: self.start.clone(),
+            kernels_only: self.kernels_only,
+            max_states: self.max_states,
+            policy: self.policy,
+            ..Grammar::new()
+        };
+
+        let mut lhs_order: Vec<SymbolId> = Vec::new();
+        for &lhs in &self.rules_lhs {
+            if !lhs_order.contains(&lhs) && !self.is_augmented(lhs) {
+                lhs_order.push(lhs);
+            }
+        }
+
+        for lhs in lhs_order {
+            // The nonterminals `lhs` derives through unit rules alone, itself
+            // first
+            let mut reached: Vec<SymbolId> = Vec::from([lhs]);
+            let mut next = 0;
+            while let Some(&symbol) = reached.get(next) {
+                next += 1;
+                for rhs in self.rules.get(&symbol).into_iter().flatten() {
+                    if is_unit(rhs) && !reached.contains(&rhs[0]) {
+                        reached.push(rhs[0]);
+                    }
+                }
+            }
+
+            for symbol in reached {
+                for rhs in self.rules.get(&symbol).into_iter().flatten() {
+                    if is_unit(rhs) {
+                        continue;
+                    }
+
+                    let rule_id = RuleId {
+                        lhs: symbol,
+                        rhs: rhs.clone(),
+                    };
+                    let name = |id: SymbolId| self.symbols.collection[id].clone();
+                    let mut rule = rhs
+                        .iter()
+                        .fold(Rule::empty(name(lhs)), |rule, &rhs| rule.rhs(name(rhs)));
+                    if let Some(&terminal) = self.precedence.rules.get(&rule_id) {
+                        rule = rule.precedence(name(terminal));
+                    }
+                    if let Some(&span @ Span::Text { .. }) = self.spans.get(&rule_id) {
+                        rule = rule.span(span);
+                    }
+                    if let Some(&weight) = self.weights.get(&rule_id) {
+                        rule = rule.weight(weight);
+                    }
+                    if let Some(label) = self.labels.get(&rule_id) {
+                        rule = rule.label(label);
+                    }
+                    grammar.add_rule(rule);
+                }
+            }
+        }
+
+        grammar
+    }
+
+    // Declares `terminal` as matching exactly `literal` instead of a token class
+    pub fn literal(&mut self, terminal: Symbol, literal: &str) -> SymbolId {
+        let terminal = self.symbols.add_symbol(terminal);
+        self.symbols.set_literal(terminal, literal);
+        terminal
+    }
+
+    // Declares the token class `terminal` as what matches `pattern`, in the
+    // syntax the lexer of `emit_lexer` understands
+    pub fn pattern(&mut self, terminal: Symbol, pattern: &str) -> SymbolId {
+        let terminal = self.symbols.add_symbol(terminal);
+        self.symbols.set_pattern(terminal, pattern);
+        terminal
+    }
+
+    // Declares `code` as what an external lexer gives for `terminal`, see
+    // `ParseTable::terminal_by_code`
+    pub fn token_code(&mut self, terminal: Symbol, code: u32) -> SymbolId {
+        let terminal = self.symbols.add_symbol(terminal);
+        self.symbols.set_code(terminal, code);
+        terminal
+    }
+
+    // Each call declares a level above all the previous ones, like the lines
+    // of `%left`, `%right` and `%nonassoc` in yacc
+    pub fn precedence(&mut self, associativity: Associativity, terminals: &[Symbol]) {
+        let terminals: Vec<SymbolId> = terminals
+            .iter()
+            .map(|terminal| self.symbols.add_symbol(terminal.clone()))
+            .collect();
+        self.precedence.declare(associativity, &terminals);
+    }
+
+    // Renames the symbol `old`, the terminal if a nonterminal has that name
+    // too, everywhere it is used. Ids stay as they were, so tables built
+    // before only differ in the names.
+    pub fn rename_symbol(&mut self, old: &str, new: &str) -> Result<(), RenameError> {
+        let symbol = self
+            .symbols
+            .id_by_name(old)
+            .ok_or_else(|| RenameError::Unknown(old.to_string()))?;
+        let previous = self.symbols.collection[symbol].clone();
+        self.symbols.rename(symbol, new)?;
+
+        if self.start.as_ref() == Some(&previous) {
+            self.start = Some(self.symbols.collection[symbol].clone());
+        }
+        Ok(())
+    }
+
+    // Taken by `infer_start` instead of guessing
+    pub fn declare_start(&mut self, start: Symbol) {
+        self.start = Some(start);
+    }
+
+    pub fn declared_start(&self) -> Option<&Symbol> {
+        self.start.as_ref()
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn rules_lhs(&self) -> &[usize] {
+        &self.rules_lhs
+    }
+
+    pub fn rules_len(&self) -> &[usize] {
+        &self.rules_len
+    }
+
+    // Each nonterminal with its alternatives joined by `|`, in the order their
+    // first rule was added, the S' rules of earlier builds left out
+    pub fn format_rules(&self) -> String {
+        let mut lhs_order: Vec<SymbolId> = Vec::new();
+        for &lhs in &self.rules_lhs {
+            if !lhs_order.contains(&lhs) && !self.is_augmented(lhs) {
+                lhs_order.push(lhs);
+            }
+        }
+
+        let mut text = String::new();
+        for lhs in lhs_order {
+            let alternatives: Vec<String> = self.rules[&lhs]
+                .iter()
+                .map(|rhs| match rhs.is_empty() {
+                    true => self.symbols.name(EPSILON).to_string(),
+                    false => rhs
+                        .iter()
+                        .map(|&symbol| self.symbols.display_name(symbol))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                })
+                .collect();
+            text.push_str(&format!(
+                "{} -> {}\n",
+                self.symbols.display_name(lhs),
+                alternatives.join(" | ")
+            ));
+        }
+        text
+    }
+
+    pub fn render_rules(&self) {
+        print!("{}", self.format_rules());
+    }
+
+    // Empty for terminals and undefined nonterminals
+    fn get_rules_by_lhs(&self, lhs: SymbolId) -> Vec<RuleId> {
+        self.rules
+            .get(&lhs)
+            .into_iter()
+            .flatten()
+            .map(|rhs| RuleId {
+                lhs,
+                rhs: rhs.clone(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn first_sets(&self) -> HashMap<SymbolId, BTreeSet<SymbolId>> {
+        let mut first_sets: HashMap<SymbolId, BTreeSet<SymbolId>> = HashMap::new();
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (&lhs, rules) in &self.rules {
+                for rhs in rules {
+                    let first = self.first_of_sequence(&first_sets, rhs);
+                    let lhs_first = first_sets.entry(lhs).or_default();
+                    for symbol in first {
+                        changed |= lhs_first.insert(symbol);
+                    }
+                }
+            }
+        }
+
+        first_sets
+    }
+
+    pub fn first(&self, symbols: &[SymbolId]) -> BTreeSet<SymbolId> {
+        self.first_of_sequence(&self.first_sets(), symbols)
+    }
+
+    // The nonterminals A with A ⇒* terminal ..., the ones with `terminal` in
+    // their FIRST set. The S' of a build are left out.
+    pub fn nonterminals_beginning_with(&self, terminal: SymbolId) -> BTreeSet<SymbolId> {
+        self.first_sets()
+            .into_iter()
+            .filter(|(nonterminal, first)| {
+                first.contains(&terminal) && !self.is_augmented(*nonterminal)
+            })
+            .map(|(nonterminal, _)| nonterminal)
+            .collect()
+    }
+
+    // FIRST(β), with ε when the whole of β is nullable
+    fn first_of_sequence(
+        &self,
+        first_sets: &HashMap<SymbolId, BTreeSet<SymbolId>>,
+        sequence: &[SymbolId],
+    ) -> BTreeSet<SymbolId> {
+        let mut first = BTreeSet::new();
+
+        for &symbol in sequence {
+            if self.symbols.is_terminal(symbol) {
+                first.insert(symbol);
+                return first;
+            }
+
+            let symbol_first = match first_sets.get(&symbol) {
+                Some(symbol_first) => symbol_first,
+                None => return first,
+            };
+
+            first.extend(symbol_first.iter().filter(|&&first| first != EPSILON));
+
+            if !symbol_first.contains(&EPSILON) {
+                return first;
+            }
+        }
+
+        first.insert(EPSILON);
+        first
+    }
+
+    // The start symbol is taken to be the lhs of the first rule, as yacc does
+    pub fn follow(&self) -> HashMap<SymbolId, BTreeSet<SymbolId>> {
+        match self.rules_lhs.first() {
+            Some(&start) => self.follow_from(start),
+            None => HashMap::new(),
+        }
+    }
+
+    fn follow_from(&self, start: SymbolId) -> HashMap<SymbolId, BTreeSet<SymbolId>> {
+        let first_sets = self.first_sets();
+        let mut follow_sets: HashMap<SymbolId, BTreeSet<SymbolId>> = self
+            .rules
+            .keys()
+            .map(|&lhs| (lhs, BTreeSet::new()))
+            .collect();
+
+        follow_sets.entry(start).or_default().insert(self.end);
+
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (&lhs, rules) in &self.rules {
+                for rhs in rules {
+                    for (position, &symbol) in rhs.iter().enumerate() {
+                        if !self.symbols.is_nonterminal(symbol) {
+                            continue;
+                        }
+
+                        let mut follow = self.first_of_sequence(&first_sets, &rhs[position + 1..]);
+                        if follow.remove(&EPSILON) {
+                            follow.extend(follow_sets[&lhs].iter().copied());
+                        }
+
+                        let symbol_follow = follow_sets.entry(symbol).or_default();
+                        for terminal in follow {
+                            changed |= symbol_follow.insert(terminal);
+                        }
+                    }
+                }
+            }
+        }
+
+        follow_sets
+    }
+
+    // [S' -> · EXPRESSION, $], without FIRST sets the generated items carry no
+    // lookahead, that is, they are LR(0) items. Items with the same rule and
+    // position are one item with the union of their lookaheads, which then
+    // has to be closed again whenever it grows. The items waiting to be closed
+    // are a set, growing again before that only adds to what it gets closed
+    // with.
+    fn closure(
+        &self,
+        set: BTreeSet<Item>,
+        first_sets: Option<&HashMap<SymbolId, BTreeSet<SymbolId>>>,
+    ) -> BTreeSet<Item> {
+        let mut items: BTreeMap<(RuleId, usize), BTreeSet<SymbolId>> = union_lookaheads(set)
+            .into_iter()
+            .map(|item| (item.core(), item.lookaheads))
+            .collect();
+        let mut to_close: BTreeSet<(RuleId, usize)> = items.keys().cloned().collect();
+
+        while let Some((rule, position)) = to_close.pop_first() {
+            let next_symbol = match rule.rhs.get(position) {
+                Some(&symbol) => symbol,
+                None => continue,
+            };
+
+            if !self.symbols.is_nonterminal(next_symbol) {
+                continue;
+            }
+
+            let lookaheads = match first_sets {
+                Some(first_sets) => {
+                    let mut lookaheads =
+                        self.first_of_sequence(first_sets, &rule.rhs[position + 1..]);
+                    if lookaheads.remove(&EPSILON) {
+                        lookaheads.extend(&items[&(rule.clone(), position)]);
+                    }
+                    lookaheads
+                }
+                None => BTreeSet::new(),
+            };
+            for new_rule in self.get_rules_by_lhs(next_symbol) {
+                let core = (new_rule, 0);
+                let new_item = !items.contains_key(&core);
+                let item_lookaheads = items.entry(core.clone()).or_default();
+                let count = item_lookaheads.len();
+                item_lookaheads.extend(&lookaheads);
+
+                if new_item || item_lookaheads.len() > count {
+                    to_close.insert(core);
+                }
+            }
+        }
+
+        items
+            .into_iter()
+            .map(|((rule, position), lookaheads)| Item {
+                rule,
+                lookaheads,
+                position,
+            })
+            .collect()
+    }
+
+    // Item sets indexed by state number and the shift/goto transitions between
+    // them, the start sets become the first states in order.
+    //
+    // A state is its kernel, the closure follows from it, so states are told
+    // apart by their kernels only, found through a hash of them. With
+    // `kernels_only` the closures are dropped once a state has been expanded
+    // and recomputed at the end, which holds far fewer items at a time on
+    // large grammars at the cost of closing every state twice.
+    fn collection(
+        &self,
+        start_sets: Vec<BTreeSet<Item>>,
+        first_sets: Option<&HashMap<SymbolId, BTreeSet<SymbolId>>>,
+    ) -> Result<(Vec<BTreeSet<Item>>, Transitions), GrammarError> {
+        let fingerprint = |kernel: &BTreeSet<Item>| {
+            let mut hasher = DefaultHasher::new();
+            kernel.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (state, kernel) in start_sets.iter().enumerate() {
+            index.entry(fingerprint(kernel)).or_default().push(state);
+        }
+        let mut states_stack: VecDeque<usize> = (0..start_sets.len()).collect();
+        let mut kernels = start_sets;
+        let mut closures: Vec<BTreeSet<Item>> = Vec::new();
+        let mut transitions: Transitions = HashMap::new();
+
+        while let Some(state) = states_stack.pop_front() {
+            let closure = self.closure(kernels[state].clone(), first_sets);
+
+            for (symbol_id, kernel) in get_new_states(&closure) {
+                let candidates = index.entry(fingerprint(&kernel)).or_default();
+                let existing = candidates
+                    .iter()
+                    .copied()
+                    .find(|&candidate| kernels[candidate] == kernel);
+
+                let next_state = match existing {
+                    Some(existing_state) => existing_state,
+                    None => {
+                        let new_state = kernels.len();
+                        if let Some(limit) = self.max_states.filter(|&limit| new_state >= limit) {
+                            return Err(GrammarError::TooManyStates(limit));
+                        }
+                        candidates.push(new_state);
+                        kernels.push(kernel);
+                        states_stack.push_back(new_state);
+                        new_state
+                    }
+                };
+
+                transitions.insert((state, symbol_id), next_state);
+            }
+
+            if !self.kernels_only {
+                closures.push(closure);
+            }
+        }
+
+        if self.kernels_only {
+            closures = kernels
+                .into_iter()
+                .map(|kernel| self.closure(kernel, first_sets))
+                .collect();
+        }
+
+        Ok((closures, transitions))
+    }
+
+    // See `collection`, off by default
+    pub fn kernels_only(&mut self, enabled: bool) {
+        self.kernels_only = enabled;
+    }
+
+    // How builds settle the conflicts precedence does not, see `ConflictPolicy`
+    pub fn conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.policy = policy;
+    }
+
+    // Builds give up with `GrammarError::TooManyStates` once the collection has
+    // more than `limit` states, for grammars that cannot be trusted to stay
+    // small. LALR(1) and minimal LR(1) count the LR(1) states before they are
+    // merged.
+    pub fn max_states(&mut self, limit: Option<usize>) {
+        self.max_states = limit;
+    }
+
+    pub(crate) fn is_augmented(&self, symbol: SymbolId) -> bool {
+        self.augmented
+            .values()
+            .any(|&augmented| augmented == symbol)
+    }
+
+    // The start has to be a symbol of the grammar, and every nonterminal it
+    // derives has to have rules
+    fn check_start(&self, start: &Symbol) -> Result<(), GrammarError> {
+        let start_id = self
+            .symbols
+            .id(start)
+            .ok_or_else(|| GrammarError::UndefinedSymbol(start.clone()))?;
+        match self.reachable_from(start_id).into_iter().find(|&symbol| {
+            self.symbols.is_nonterminal(symbol) && !self.rules.contains_key(&symbol)
+        }) {
+            Some(symbol) => Err(GrammarError::NoRules(symbol)),
+            None => Ok(()),
+        }
+    }
+
+    // S' -> start, named after the start symbol with as many primes as it
+    // takes to not clash with a symbol of the grammar
+    fn augment(&mut self, start: Symbol) -> RuleId {
+        let start_id = self.symbols.add_symbol(start.clone());
+        if let Some(&lhs) = self.augmented.get(&start_id) {
+            return RuleId {
+                lhs,
+                rhs: Vec::from([start_id]),
+            };
+        }
+
+        let mut name = format!("{}'", self.symbols.name(start_id));
+        while self
+            .symbols
+            .id(&Symbol::Nonterminal(name.clone()))
+            .is_some()
+            || self.symbols.id(&Symbol::Terminal(name.clone())).is_some()
+        {
+            name.push('\'');
+        }
+
+        let rule = self.add_rule(Rule::new(Symbol::Nonterminal(name), start));
+        self.augmented.insert(start_id, rule.lhs);
+        rule
+    }
+
+    // Every conflict of the canonical LR(1) table, none when `build` would
+    // succeed or fails for another reason
+    pub fn analyze_conflicts(&mut self, start: Symbol) -> Vec<Conflict> {
+        match self.build(start, Mode::Lr1) {
+            Err(GrammarError::Conflicts(conflicts)) => conflicts,
+            _ => Vec::new(),
+        }
+    }
+
+    // The LR(0) automaton from `start`, its states recognize the viable
+    // prefixes and are the ones SLR(1) and LALR(1) tables are built on. The
+    // items have no lookaheads and no table is built, so there is no
+    // conflict to fail on, only `max_states`.
+    pub fn lr0_automaton(&mut self, start: Symbol) -> Result<ItemGraph, GrammarError> {
+        self.check_start(&start)?;
+        let rule = self.augment(start);
+        let start_set = BTreeSet::from([Item {
+            rule,
+            position: 0,
+            lookaheads: BTreeSet::new(),
+        }]);
+
+        let (states, transitions) = self.collection(Vec::from([start_set]), None)?;
+        Ok(ItemGraph {
+            states,
+            transitions,
+        })
+    }
+
+    pub fn build(&mut self, start: Symbol, mode: Mode) -> Result<ParseTable, GrammarError> {
+        self.build_starts(&[start], mode)
+    }
+
+    // Builds the starts of `previous` again after rules were added, with the
+    // states that are still there keeping the numbers they had, see
+    // `ParseTable::keep_numbers`. `previous` has to come from this grammar.
+    // The whole collection is built again, only the numbering is carried over.
+    pub fn rebuild(
+        &mut self,
+        previous: &ParseTable,
+        mode: Mode,
+    ) -> Result<ParseTable, GrammarError> {
+        let starts: Vec<Symbol> = previous
+            .starts
+            .iter()
+            .map(|&(start, _)| self.symbols.collection[start].clone())
+            .collect();
+
+        let mut table = self.build_starts(&starts, mode)?;
+        table.keep_numbers(previous);
+        Ok(table)
+    }
+
+    // One table recognizing each of `starts`, the start state of `starts[i]`
+    // is state i and the states past them are shared
+    pub fn build_starts(
+        &mut self,
+        starts: &[Symbol],
+        mode: Mode,
+    ) -> Result<ParseTable, GrammarError> {
+        for start in starts {
+            self.check_start(start)?;
+        }
+        let rules: Vec<RuleId> = starts
+            .iter()
+            .map(|start| self.augment(start.clone()))
+            .collect();
+        let augmented: Vec<SymbolId> = rules.iter().map(|rule| rule.lhs).collect();
+        let first_sets = self.first_sets();
+
+        let (first_sets, lookaheads) = match mode {
+            Mode::Lr0 | Mode::Slr1 => (None, BTreeSet::new()),
+            Mode::Lalr1 | Mode::MinimalLr1 | Mode::Lr1 => {
+                (Some(&first_sets), BTreeSet::from([self.end]))
+            }
+        };
+        let start_sets: Vec<BTreeSet<Item>> = rules
+            .iter()
+            .map(|rule| {
+                BTreeSet::from([Item {
+                    rule: rule.clone(),
+                    position: 0,
+                    lookaheads: lookaheads.clone(),
+                }])
+            })
+            .collect();
+
+        let (mut states, mut transitions) = self.collection(start_sets, first_sets)?;
+        match mode {
+            Mode::Lalr1 => (states, transitions) = merge_cores(states, transitions),
+            Mode::MinimalLr1 => (states, transitions) = merge_compatible(states, transitions),
+            _ => {}
+        }
+
+        let terminals: Vec<SymbolId> = (0..self.symbols.len())
+            .filter(|&symbol| self.symbols.is_terminal(symbol))
+            .collect();
+        // Every augmented symbol is followed by $ alone, so the FOLLOW sets
+        // from each start only add up
+        let mut follow_sets: HashMap<SymbolId, BTreeSet<SymbolId>> = HashMap::new();
+        if mode == Mode::Slr1 {
+            for &start_lhs in &augmented {
+                for (symbol, follow) in self.follow_from(start_lhs) {
+                    follow_sets.entry(symbol).or_default().extend(follow);
+                }
+            }
+        }
+
+        let built = build_actions(
+            &states,
+            &transitions,
+            &self.symbols,
+            &augmented,
+            &self.precedence,
+            self.policy,
+            &self.spans,
+            &self.rule_ids,
+            |item| match mode {
+                _ if augmented.contains(&item.rule.lhs) => Vec::from([self.end]),
+                Mode::Lr0 => terminals.clone(),
+                Mode::Slr1 => follow_sets[&item.rule.lhs].iter().copied().collect(),
+                Mode::Lalr1 | Mode::MinimalLr1 | Mode::Lr1 => {
+                    item.lookaheads.iter().copied().collect()
+                }
+            },
+        );
+
+        if !built.conflicts.is_empty() {
+            return Err(GrammarError::Conflicts(built.conflicts));
+        }
+
+        Ok(ParseTable {
+            symbols: self.symbols.clone(),
+            end: self.end,
+            starts: rules.iter().map(|rule| (rule.rhs[0], rule.lhs)).collect(),
+            precedence: self.precedence.clone(),
+            policy: self.policy,
+            spans: self.spans.clone(),
+            labels: self.labels.clone(),
+            states,
+            transitions,
+            actions: built.actions,
+            rules_lhs: self.rules_lhs.clone(),
+            rules_len: self.rules_len.clone(),
+            rule_ids: self.rule_ids.clone(),
+            resolved: built.resolved,
+            defaulted: built.defaulted,
+        })
+    }
+}
+
+impl Default for Grammar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mode;
+    use crate::parser::{Parser, Tree};
+    use crate::precedence::{Associativity, Resolution};
+    use crate::symbols::{EPSILON, Symbol};
+    use crate::table::{Action, Conflict, GrammarError, ParseTable};
+    use crate::text::parse_grammar;
+
+    fn tokens(table: &ParseTable, input: &[&str]) -> Vec<usize> {
+        input
+            .iter()
+            .map(|name| table.symbols().id_by_name(name).unwrap())
+            .collect()
+    }
+
+    // Each grammar builds in every mode into a table that accepts exactly the
+    // `accepted` token sequences among `inputs`
+    fn check(text: &str, states: usize, inputs: &[(&[&str], bool)]) {
+        for mode in [
+            Mode::Lr0,
+            Mode::Slr1,
+            Mode::Lalr1,
+            Mode::MinimalLr1,
+            Mode::Lr1,
+        ] {
+            let mut grammar = parse_grammar(text).unwrap();
+            let table = grammar
+                .build(Symbol::Nonterminal("S".to_string()), mode)
+                .unwrap();
+            assert_eq!(table.states_len(), states, "{text:?} in {mode:?}");
+
+            for &(input, accepted) in inputs {
+                let tokens = tokens(&table, input);
+                assert_eq!(table.recognizes(&tokens), accepted, "{input:?} in {mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn single_terminal_start() {
+        check(
+            "S -> a",
+            3,
+            &[(&["a"], true), (&[], false), (&["a", "a"], false)],
+        );
+    }
+
+    #[test]
+    fn terminal_sequence_start() {
+        check(
+            "S -> a b",
+            4,
+            &[
+                (&["a", "b"], true),
+                (&["a"], false),
+                (&["b"], false),
+                (&["a", "b", "b"], false),
+            ],
+        );
+    }
+
+    #[test]
+    fn left_recursive_start() {
+        check(
+            "S -> S a | a",
+            4,
+            &[(&["a"], true), (&["a", "a", "a"], true), (&[], false)],
+        );
+    }
+
+    // `E plus E` reduces on `plus`, the shift it beat is still an edge
+    #[test]
+    fn settled_shift_stays_in_the_item_graph() {
+        let mut grammar = parse_grammar("S -> E\nE -> E plus E | id").unwrap();
+        grammar.precedence(Associativity::Left, &[Symbol::Terminal("plus".to_string())]);
+        let table = grammar
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+        let plus = table.symbols().id_by_name("plus").unwrap();
+
+        let graph = table.item_graph();
+        let settled = (0..table.states_len()).find(|&state| {
+            matches!(table.action(state, plus), Some(Action::Reduce(_)))
+                && graph.transitions.contains_key(&(state, plus))
+        });
+        assert!(settled.is_some());
+        assert!(table.to_dot().contains("style=dashed"));
+
+        let read_back = ParseTable::from_bytes(&table.to_bytes()).unwrap();
+        assert_eq!(read_back.item_graph().transitions, graph.transitions);
+    }
+
+    #[test]
+    fn summary_leaves_out_augmented_rules() {
+        let mut grammar = parse_grammar("S -> S a | a").unwrap();
+        let before = grammar.summary();
+        grammar
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+
+        assert_eq!(before.rules, 2);
+        assert_eq!(grammar.summary(), before);
+    }
+
+    #[test]
+    fn validate_after_build() {
+        let mut grammar = parse_grammar("S -> A b\nA -> a").unwrap();
+        let start = Symbol::Nonterminal("S".to_string());
+        let before = grammar.validate(&start);
+        grammar.build(start.clone(), Mode::Lalr1).unwrap();
+
+        assert!(before.is_ok());
+        assert_eq!(grammar.validate(&start), before);
+    }
+
+    #[test]
+    fn unreachable_symbols_after_build() {
+        let mut grammar = parse_grammar("S -> a\nB -> b").unwrap();
+        let start = Symbol::Nonterminal("S".to_string());
+        let before = grammar.unreachable_symbols(&start);
+        grammar.build(start.clone(), Mode::Lalr1).unwrap();
+
+        let names: Vec<&str> = before
+            .iter()
+            .map(|&symbol| grammar.symbols().name(symbol))
+            .collect();
+        assert_eq!(names, ["B", "b"]);
+        assert_eq!(grammar.unreachable_symbols(&start), before);
+    }
+
+    // The dragon book grammar 4.55, LALR merges the states of `C` that LR(1)
+    // tells apart by their lookahead
+    #[test]
+    fn lr1_and_lalr_state_counts() {
+        let text = "S -> C C\nC -> c C | d";
+        for (mode, states) in [(Mode::Lr1, 10), (Mode::Lalr1, 7)] {
+            let table = parse_grammar(text)
+                .unwrap()
+                .build(Symbol::Nonterminal("S".to_string()), mode)
+                .unwrap();
+            assert_eq!(table.states_len(), states, "{mode:?}");
+            assert!(table.recognizes(&tokens(&table, &["c", "d", "d"])));
+            assert!(!table.recognizes(&tokens(&table, &["c", "c"])));
+        }
+    }
+
+    // FIRST of a sequence goes on past nullable symbols, and has ε when all of
+    // them are
+    #[test]
+    fn first_through_nullable_nonterminals() {
+        let grammar = parse_grammar("S -> A B c\nA -> a |\nB -> b |").unwrap();
+        let id = |name| grammar.symbols().id_by_name(name).unwrap();
+        let (a, b, c) = (id("a"), id("b"), id("c"));
+        let (nonterminal_a, nonterminal_b) = (id("A"), id("B"));
+
+        assert_eq!(grammar.first(&[nonterminal_a]), [a, EPSILON].into());
+        assert_eq!(
+            grammar.first(&[nonterminal_a, nonterminal_b]),
+            [a, b, EPSILON].into()
+        );
+        assert_eq!(grammar.first(&[id("S")]), [a, b, c].into());
+        assert_eq!(grammar.first(&[]), [EPSILON].into());
+    }
+
+    // `B` may be empty, so what follows `S` also follows the `A` before it
+    #[test]
+    fn follow_through_a_nullable_suffix() {
+        let grammar = parse_grammar("S -> a A B\nA -> x\nB -> b |").unwrap();
+        let id = |name| grammar.symbols().id_by_name(name).unwrap();
+        let follow = grammar.follow();
+
+        assert_eq!(follow[&id("S")], [grammar.end].into());
+        assert_eq!(follow[&id("A")], [id("b"), grammar.end].into());
+        assert_eq!(follow[&id("B")], [grammar.end].into());
+    }
+
+    #[test]
+    fn shift_reduce_conflict() {
+        let mut grammar = parse_grammar("E -> E '+' E | n").unwrap();
+        let built = grammar.build(Symbol::Nonterminal("E".to_string()), Mode::Lalr1);
+        let Err(GrammarError::Conflicts(conflicts)) = built else {
+            panic!("expected a conflict");
+        };
+
+        let [
+            Conflict::ShiftReduce {
+                state,
+                symbol,
+                actions,
+                ..
+            },
+        ] = conflicts.as_slice()
+        else {
+            panic!("{conflicts:?}");
+        };
+        assert_eq!((*state, symbol.as_str()), (4, "+"));
+        assert!(actions.contains(&Action::Shift(3)));
+        assert!(actions.contains(&Action::Reduce(0)));
+    }
+
+    // After `a` both `A -> a` and `B -> a` reduce on `x` and on `y`
+    #[test]
+    fn reduce_reduce_conflict() {
+        let mut grammar = parse_grammar("S -> A x | B y | A y | B x\nA -> a\nB -> a").unwrap();
+        let built = grammar.build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1);
+        let Err(GrammarError::Conflicts(conflicts)) = built else {
+            panic!("expected a conflict");
+        };
+        let id = |name| grammar.symbols().id_by_name(name).unwrap();
+
+        let symbols: Vec<&str> = conflicts
+            .iter()
+            .map(|conflict| match conflict {
+                Conflict::ReduceReduce {
+                    rules,
+                    lookaheads,
+                    symbol,
+                    ..
+                } => {
+                    let rules: Vec<String> = rules
+                        .iter()
+                        .map(|rule| rule.format(grammar.symbols()))
+                        .collect();
+                    assert_eq!(rules, ["A -> a", "B -> a"]);
+                    assert_eq!(lookaheads, &[id("x"), id("y")].into());
+                    symbol.as_str()
+                }
+                Conflict::ShiftReduce { .. } => panic!("{conflict:?}"),
+            })
+            .collect();
+        assert_eq!(symbols, ["x", "y"]);
+    }
+
+    // The dragon book grammar 4.49, FOLLOW(R) has `=` so SLR also reduces
+    // `R -> L` where only the shift of `=` is right
+    #[test]
+    fn lalr_builds_what_slr_cannot() {
+        let text = "S -> L '=' R | R\nL -> '*' R | id\nR -> L";
+        let start = Symbol::Nonterminal("S".to_string());
+
+        let slr = parse_grammar(text)
+            .unwrap()
+            .build(start.clone(), Mode::Slr1);
+        assert!(matches!(slr, Err(GrammarError::Conflicts(_))));
+
+        let table = parse_grammar(text)
+            .unwrap()
+            .build(start, Mode::Lalr1)
+            .unwrap();
+        assert!(table.recognizes(&tokens(&table, &["id", "=", "*", "id"])));
+        assert!(!table.recognizes(&tokens(&table, &["id", "=", "="])));
+    }
+
+    #[test]
+    fn precedence_nests_the_tighter_operator() {
+        let text = "%left '+'\n%left '*'\nE -> E '+' E | E '*' E | n";
+        let mut grammar = parse_grammar(text).unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("E".to_string()), Mode::Lalr1)
+            .unwrap();
+
+        let mut resolved: Vec<(String, &str, Resolution)> = table
+            .resolved_conflicts()
+            .iter()
+            .map(|conflict| {
+                (
+                    conflict.rule.format(table.symbols()),
+                    table.symbols().name(conflict.symbol),
+                    conflict.resolution,
+                )
+            })
+            .collect();
+        resolved.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        assert_eq!(
+            resolved,
+            [
+                ("E -> E * E".to_string(), "*", Resolution::Reduce),
+                ("E -> E * E".to_string(), "+", Resolution::Reduce),
+                ("E -> E + E".to_string(), "*", Resolution::Shift),
+                ("E -> E + E".to_string(), "+", Resolution::Reduce),
+            ]
+        );
+
+        let input = tokens(&table, &["n", "+", "n", "*", "n"]);
+        let tree = Parser::new(&table).parse(&input).unwrap();
+        let id = |name| table.symbols().id_by_name(name).unwrap();
+        let n = || Tree::Node(id("E"), vec![Tree::Leaf(id("n"))]);
+        let product = Tree::Node(id("E"), vec![n(), Tree::Leaf(id("*")), n()]);
+        assert_eq!(
+            tree,
+            Tree::Node(id("E"), vec![n(), Tree::Leaf(id("+")), product])
+        );
+    }
+
+    fn build_error(text: &str, start: &str, max_states: Option<usize>) -> GrammarError {
+        let mut grammar = parse_grammar(text).unwrap();
+        grammar.max_states(max_states);
+        match grammar.build(Symbol::Nonterminal(start.to_string()), Mode::Lalr1) {
+            Ok(_) => panic!("{text:?} built"),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn undefined_start_is_an_error() {
+        assert_eq!(
+            build_error("S -> a", "T", None),
+            GrammarError::UndefinedSymbol(Symbol::Nonterminal("T".to_string()))
+        );
+    }
+
+    #[test]
+    fn nonterminal_without_rules_is_an_error() {
+        let error = build_error("S -> A b", "S", None);
+        let grammar = parse_grammar("S -> A b").unwrap();
+        let a = grammar.symbols().id_by_name("A").unwrap();
+        assert_eq!(error, GrammarError::NoRules(a));
+    }
+
+    // LALR counts the 10 LR(1) states of `S -> C C` before they merge into 7
+    #[test]
+    fn too_many_states_is_an_error() {
+        let text = "S -> C C\nC -> c C | d";
+        assert_eq!(
+            build_error(text, "S", Some(3)),
+            GrammarError::TooManyStates(3)
+        );
+
+        let mut grammar = parse_grammar(text).unwrap();
+        grammar.max_states(Some(10));
+        let built = grammar.build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1);
+        assert!(built.is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::grammar::{Grammar, RuleId};
+use crate::symbols::{EPSILON, SymbolId, Symbols};
+
+#[derive(Hash, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct Item {
+    pub rule: RuleId,
+    // Empty for the LR(0) items of the LR(0) and SLR(1) constructions
+    pub lookaheads: BTreeSet<SymbolId>,
+    pub position: usize,
+}
+
+impl Item {
+    pub fn format(&self, symbols: &Symbols) -> String {
+        let mut text = format!("[{} ->", symbols.display_name(self.rule.lhs));
+        let mut rhs_names: Vec<Cow<str>> = self
+            .rule
+            .rhs
+            .iter()
+            .map(|&rhs| symbols.display_name(rhs))
+            .collect();
+        rhs_names.insert(self.position, Cow::Borrowed("·"));
+        for rhs in rhs_names {
+            text.push(' ');
+            text.push_str(&rhs);
+        }
+        if !self.lookaheads.is_empty() {
+            let lookaheads: Vec<Cow<str>> = self
+                .lookaheads
+                .iter()
+                .map(|&lookahead| symbols.display_name(lookahead))
+                .collect();
+            text.push_str(&format!(", {}", lookaheads.join("/")));
+        }
+        text.push(']');
+        text
+    }
+
+    pub fn render(&self, symbols: &Symbols) {
+        println!("{}", self.format(symbols));
+    }
+
+    pub fn end(&self) -> bool {
+        self.position >= self.rule.rhs.len()
+    }
+
+    pub(crate) fn advanced(&self) -> Self {
+        let mut new_item = self.clone();
+        new_item.position += 1;
+        new_item
+    }
+
+    // Terminals that can come right after the dot, FIRST of the rest of the
+    // rule and, when the rest is nullable, the lookaheads, or FOLLOW of the
+    // lhs for an LR(0) item
+    pub fn valid_lookaheads(&self, grammar: &Grammar) -> BTreeSet<SymbolId> {
+        let mut valid = grammar.first(&self.rule.rhs[self.position..]);
+
+        if valid.remove(&EPSILON) {
+            if self.lookaheads.is_empty() {
+                let follow = grammar.follow().remove(&self.rule.lhs);
+                valid.extend(follow.into_iter().flatten());
+            } else {
+                valid.extend(&self.lookaheads);
+            }
+        }
+
+        valid
+    }
+
+    pub(crate) fn core(&self) -> (RuleId, usize) {
+        (self.rule.clone(), self.position)
+    }
+
+    pub fn next_symbol(&self) -> Option<SymbolId> {
+        if let Some(symbol) = self.rule.rhs.get(self.position) {
+            return Some(*symbol);
+        }
+
+        None
+    }
+}
+
+// A hash of the (rule, position) pairs of `set`, the same for item sets that
+// only differ in their lookaheads. Different cores may still collide.
+pub fn core_key(set: &BTreeSet<Item>) -> u64 {
+    let core: BTreeSet<(RuleId, usize)> = set.iter().map(Item::core).collect();
+    let mut hasher = DefaultHasher::new();
+    core.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Items with the same rule and position become one with all their lookaheads
+pub(crate) fn union_lookaheads(items: impl IntoIterator<Item = Item>) -> BTreeSet<Item> {
+    let mut lookaheads: BTreeMap<(RuleId, usize), BTreeSet<SymbolId>> = BTreeMap::new();
+    for item in items {
+        lookaheads
+            .entry(item.core())
+            .or_default()
+            .extend(item.lookaheads);
+    }
+
+    lookaheads
+        .into_iter()
+        .map(|((rule, position), lookaheads)| Item {
+            rule,
+            lookaheads,
+            position,
+        })
+        .collect()
+}
+
+// The kernels reached from `set` by its next symbols, ordered by symbol so the
+// states get the same numbers on every build
+pub(crate) fn get_new_states(set: &BTreeSet<Item>) -> BTreeMap<SymbolId, BTreeSet<Item>> {
+    let mut new_states: BTreeMap<SymbolId, BTreeSet<Item>> = BTreeMap::new();
+
+    for item in set {
+        let next_symbol = match item.next_symbol() {
+            Some(symbol) => symbol,
+            None => continue,
+        };
+
+        let new_item = item.advanced();
+
+        if let Some(new_set) = new_states.get_mut(&next_symbol) {
+            new_set.insert(new_item);
+        } else {
+            let new_set = BTreeSet::from([new_item]);
+            new_states.insert(next_symbol, new_set);
+        }
+    }
+
+    new_states
+}
@@ -0,0 +1,30 @@
+mod analysis;
+mod automaton;
+mod binary;
+mod codegen;
+mod compact;
+mod diff;
+mod dot;
+mod generate;
+mod grammar;
+mod item;
+mod pairs;
+mod parser;
+mod precedence;
+mod symbols;
+mod table;
+mod text;
+
+pub use analysis::{GrammarClass, StartError, Summary, Validation};
+pub use automaton::ItemGraph;
+pub use binary::DecodeError;
+pub use codegen::TemplateError;
+pub use compact::CompactTable;
+pub use diff::{GrammarDiff, NamedRule};
+pub use grammar::{Grammar, Mode, Rule, RuleId, Span};
+pub use item::{Item, core_key};
+pub use parser::{Actions, Parser, PushParser, Recovery, SyntaxError, Tree};
+pub use precedence::{Associativity, ConflictPolicy, Resolution, ResolvedConflict};
+pub use symbols::{EPSILON, RenameError, Symbol, SymbolId, Symbols};
+pub use table::{Action, Conflict, ConflictSummary, GrammarError, ParseTable};
+pub use text::{ParseError, WriteError, parse_grammar};
@@ -1,364 +1,126 @@
-use std::{
-    collections::{BTreeSet, HashMap, VecDeque},
-    usize,
-};
+use std::io::{self, Write};
+use std::{env, fs, process};
 
-type SymbolId = usize;
-type Rhs = Vec<SymbolId>;
+use lrgen::{ConflictSummary, GrammarError, Mode, parse_grammar};
 
-#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
-enum Symbol {
-    Terminal(String),
-    Nonterminal(String),
-}
-
-struct Symbols {
-    collection: Vec<Symbol>,
-    index: HashMap<Symbol, SymbolId>,
-}
-
-impl Symbols {
-    fn new() -> Symbols {
-        Symbols {
-            collection: Vec::new(),
-            index: HashMap::new(),
-        }
-    }
-
-    fn name(&self, symbol_id: SymbolId) -> &str {
-        match &self.collection[symbol_id] {
-            Symbol::Terminal(name) => name,
-            Symbol::Nonterminal(name) => name,
-        }
-    }
-
-    fn add_symbol(&mut self, symbol: Symbol) -> SymbolId {
-        match self.index.get(&symbol) {
-            Some(&id) => id,
-            None => {
-                let id = self.collection.len();
-                self.index.insert(symbol.clone(), id);
-                self.collection.push(symbol);
-                id
-            }
-        }
-    }
-
-    fn is_terminal(&self, symbol_id: SymbolId) -> bool {
-        let symbol = match self.collection.get(symbol_id) {
-            Some(symbol) => symbol,
-            None => return false,
-        };
+const USAGE: &str = concat!(
+    "usage: lrgen [--mode lr0|slr|lalr|minimal|lr1]",
+    " [--format text|dot|markdown|rust|rust-hashed|c|json] [--max-states N] <grammar>"
+);
 
-        matches!(symbol, Symbol::Terminal(_))
-    }
-
-    fn is_nonterminal(&self, symbol_id: SymbolId) -> bool {
-        let symbol = match self.collection.get(symbol_id) {
-            Some(symbol) => symbol,
-            None => return false,
-        };
-
-        matches!(symbol, Symbol::Nonterminal(_))
-    }
-}
-
-struct Rule {
-    lhs: Symbol,
-    rhs: Vec<Symbol>,
-}
+const FORMATS: [&str; 7] = [
+    "text",
+    "dot",
+    "markdown",
+    "rust",
+    "rust-hashed",
+    "c",
+    "json",
+];
 
-impl Rule {
-    fn new(lhs: Symbol, rhs: Symbol) -> Self {
-        Rule {
-            lhs,
-            rhs: Vec::from([rhs]),
-        }
-    }
-
-    fn rhs(mut self, rhs: Symbol) -> Self {
-        self.rhs.push(rhs);
-        self
-    }
+fn fail(message: &str) -> ! {
+    eprintln!("lrgen: {message}");
+    process::exit(1);
 }
 
-#[derive(Hash, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct RuleId {
-    lhs: SymbolId,
-    rhs: Vec<SymbolId>,
-}
-
-#[derive(Hash, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Item {
-    rule: RuleId,
-    lookahead: SymbolId,
-    position: usize,
-}
-
-impl Item {
-    fn render(&self, symbols: &Symbols) {
-        print!("[{} ->", symbols.name(self.rule.lhs));
-        let mut rhs_names: Vec<&str> = self.rule.rhs.iter().map(|&rhs| symbols.name(rhs)).collect();
-        rhs_names.insert(self.position, "·");
-        for rhs in rhs_names {
-            print!(" {}", rhs);
-        }
-        println!(", {}]", symbols.name(self.lookahead));
-    }
-
-    fn end(&self) -> bool {
-        self.position >= self.rule.rhs.len()
-    }
-
-    fn lookahead(&self, symbols: &Symbols) -> SymbolId {
-        for &symbol in self.rule.rhs[self.position + 1..].iter() {
-            if symbols.is_terminal(symbol) {
-                return symbol;
+fn main() {
+    let mut mode = Mode::Lr1;
+    let mut format = "text".to_string();
+    let mut max_states = None;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mode" => {
+                mode = match args.next().as_deref() {
+                    Some("lr0") => Mode::Lr0,
+                    Some("slr") => Mode::Slr1,
+                    Some("lalr") => Mode::Lalr1,
+                    Some("minimal") => Mode::MinimalLr1,
+                    Some("lr1") => Mode::Lr1,
+                    _ => fail(USAGE),
+                }
             }
-        }
-
-        self.lookahead
-    }
-
-    fn advanced(&self) -> Self {
-        let mut new_item = self.clone();
-        new_item.position += 1;
-        new_item
-    }
-
-    fn next_symbol(&self) -> Option<SymbolId> {
-        if let Some(symbol) = self.rule.rhs.get(self.position) {
-            return Some(*symbol);
-        }
-
-        None
-    }
-}
-
-fn get_new_states(set: &BTreeSet<Item>) -> HashMap<SymbolId, BTreeSet<Item>> {
-    let mut new_states: HashMap<SymbolId, BTreeSet<Item>> = HashMap::new();
-
-    for item in set {
-        let next_symbol = match item.next_symbol() {
-            Some(symbol) => symbol,
-            None => continue,
-        };
-
-        let new_item = item.advanced();
-
-        if let Some(new_set) = new_states.get_mut(&next_symbol) {
-            new_set.insert(new_item);
-        } else {
-            let new_set = BTreeSet::from([new_item]);
-            new_states.insert(next_symbol, new_set);
-        }
-    }
-
-    new_states
-}
-
-fn render_actions(actions: &HashMap<SymbolId, Action>, symbols: &Symbols) {
-    for (&symbol, action) in actions {
-        action.render(symbols.name(symbol));
-    }
-}
-
-fn render_states(
-    states: &HashMap<BTreeSet<Item>, usize>,
-    actions: &Vec<HashMap<SymbolId, Action>>,
-    symbols: &Symbols,
-) {
-    for (set, &number) in states {
-        println!("\n{number}");
-        println!("--- items ---");
-        for item in set {
-            item.render(&symbols);
-        }
-        println!("--- actions ---");
-        render_actions(&actions[number], symbols);
-    }
-}
-
-enum Action {
-    Goto(usize),
-    Shift(usize),
-    Reduce(usize, usize),
-}
-
-impl Action {
-    fn render(&self, symbol: &str) {
-        match self {
-            Self::Goto(next_state) => println!("goto({symbol}, {next_state})"),
-            Self::Shift(next_state) => println!("shift({symbol}, {next_state})"),
-            Self::Reduce(rhs_len, lhs) => println!("reduce({symbol}, {rhs_len}, {lhs})"),
-        }
-    }
-}
-
-struct Grammar {
-    symbols: Symbols,
-    rules: HashMap<usize, Vec<Rhs>>,
-    rules_lhs: Vec<usize>,
-    rules_len: Vec<usize>,
-}
-
-impl Grammar {
-    fn new() -> Self {
-        Grammar {
-            symbols: Symbols::new(),
-            rules: HashMap::new(),
-            rules_lhs: Vec::new(),
-            rules_len: Vec::new(),
+            "--format" => match args.next() {
+                Some(value) if FORMATS.contains(&value.as_str()) => format = value,
+                _ => fail(USAGE),
+            },
+            "--max-states" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(limit) => max_states = Some(limit),
+                None => fail(USAGE),
+            },
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => fail(USAGE),
         }
     }
+    let path = path.unwrap_or_else(|| fail(USAGE));
 
-    fn add_rule(&mut self, rule: Rule) -> RuleId {
-        let lhs = self.symbols.add_symbol(rule.lhs);
-        let rhs: Vec<SymbolId> = rule
-            .rhs
-            .into_iter()
-            .map(|rhs| self.symbols.add_symbol(rhs))
-            .collect();
-
-        if let Some(rules) = self.rules.get_mut(&lhs) {
-            rules.push(rhs.clone());
-        } else {
-            self.rules.insert(lhs, Vec::from([rhs.clone()]));
-        }
-
-        self.rules_lhs.push(lhs);
-        self.rules_len.push(rhs.len());
-
-        RuleId { lhs, rhs }
-    }
+    let text = fs::read_to_string(&path).unwrap_or_else(|error| fail(&format!("{path}: {error}")));
+    let mut grammar = parse_grammar(&text).unwrap_or_else(|error| fail(&format!("{path}:{error}")));
+    let start = grammar
+        .infer_start()
+        .unwrap_or_else(|error| fail(&format!("{path}: {error}")));
+    grammar.max_states(max_states);
 
-    fn get_rules_by_lhs(&self, lhs: SymbolId) -> Vec<RuleId> {
-        self.rules[&lhs]
+    if let Some(cycle) = grammar.has_cycle() {
+        let names: Vec<&str> = cycle
             .iter()
-            .map(|rhs| RuleId {
-                lhs,
-                rhs: rhs.clone(),
-            })
-            .collect()
-    }
-
-    // [S' -> · EXPRESSION, $]
-    fn closure(&self, mut set: BTreeSet<Item>) -> BTreeSet<Item> {
-        let mut to_close: Vec<Item> = set.iter().cloned().collect();
-
-        while let Some(item_to_close) = to_close.pop() {
-            let next_symbol = match item_to_close.next_symbol() {
-                Some(symbol) => symbol,
-                None => continue,
-            };
-
-            if !self.symbols.is_nonterminal(next_symbol) {
-                continue;
-            }
-
-            let lookahead = item_to_close.lookahead(&self.symbols);
-            for rule in self.get_rules_by_lhs(next_symbol) {
-                let new_item = Item {
-                    rule,
-                    position: 0,
-                    lookahead,
-                };
-
-                if set.insert(new_item.clone()) {
-                    to_close.push(new_item);
-                }
+            .map(|&symbol| grammar.symbols().name(symbol))
+            .collect();
+        eprintln!(
+            "lrgen: {path}: warning: cyclic grammar: {} -> {}",
+            names.join(" -> "),
+            names[0]
+        );
+    }
+    for nonterminal in grammar.hidden_left_recursion() {
+        let name = grammar.symbols().name(nonterminal);
+        eprintln!("lrgen: {path}: warning: hidden left recursion: {name}");
+    }
+
+    let table = match grammar.build(start, mode) {
+        Ok(table) => table,
+        Err(GrammarError::Conflicts(conflicts)) => {
+            for conflict in &conflicts {
+                eprint!("{}", conflict.format(grammar.symbols()));
             }
+            fail(&format!("{path}: {}", ConflictSummary::new(&conflicts)));
         }
+        Err(GrammarError::NoRules(symbol)) => {
+            let name = grammar.symbols().name(symbol);
+            fail(&format!("{path}: nonterminal {name} has no rules"))
+        }
+        Err(error) => fail(&format!("{path}: {error}")),
+    };
 
-        set
-    }
-
-    fn compact(
-        &self,
-        states: &HashMap<BTreeSet<Item>, usize>,
-        actions: &Vec<HashMap<SymbolId, Action>>,
-    ) {
+    for rule in table.unreachable_rules() {
+        if let Some(rule) = table.rule(rule) {
+            let rule = rule.format(table.symbols());
+            eprintln!("lrgen: {path}: warning: rule never reduced: {rule}");
+        }
     }
 
-    fn build(&mut self, start: Symbol) {
-        let rule = self.add_rule(Rule::new(Symbol::Nonterminal("S'".to_string()), start));
-        let lookahead = self.symbols.add_symbol(Symbol::Terminal("$".to_string()));
-        let start_production = Item {
-            rule,
-            position: 0,
-            lookahead,
-        };
-
-        let mut states: HashMap<BTreeSet<Item>, usize> = HashMap::new();
-        let mut states_stack: VecDeque<BTreeSet<Item>> =
-            VecDeque::from([self.closure(BTreeSet::from([start_production]))]);
-
-        let mut actions: Vec<HashMap<SymbolId, Action>> = Vec::new();
-
-        while let Some(set) = states_stack.pop_front() {
-            let mut new_actions: HashMap<SymbolId, Action> = HashMap::new();
-            let new_states = get_new_states(&set);
-
-            for item in &set {
-                if item.end() {
-                    new_actions.insert(
-                        item.lookahead,
-                        Action::Reduce(item.rule.rhs.len(), item.rule.lhs),
-                    );
-                }
-            }
-
-            for (symbol_id, mut new_set) in new_states {
-                new_set = self.closure(new_set);
-
-                let next_state = match states.get(&new_set) {
-                    Some(&existing_state) => existing_state,
-                    None => states.len() + states_stack.len() + 1,
-                };
-
-                let action = match self.symbols.collection[symbol_id] {
-                    Symbol::Terminal(_) => Action::Shift(next_state),
-                    Symbol::Nonterminal(_) => Action::Goto(next_state),
-                };
-
-                new_actions.insert(symbol_id, action);
-                states_stack.push_back(new_set);
-            }
-
-            if !states.contains_key(&set) {
-                states.insert(set, states.len());
-                actions.push(new_actions);
-            }
+    let mut stdout = io::stdout().lock();
+    let written = match format.as_str() {
+        "text" => stdout.write_all(table.format().as_bytes()),
+        "dot" => stdout.write_all(table.to_dot().as_bytes()),
+        "markdown" => stdout.write_all(table.to_markdown().as_bytes()),
+        "rust" | "rust-hashed" => match format.as_str() {
+            "rust" => table.emit_rust(&mut stdout),
+            _ => table.emit_rust_hashed(&mut stdout),
         }
-
-        render_states(&states, &actions, &self.symbols);
+        .and_then(|()| writeln!(stdout))
+        .and_then(|()| table.emit_token_enum(&mut stdout))
+        .and_then(|()| writeln!(stdout))
+        .and_then(|()| table.emit_lexer(&mut stdout)),
+        "c" => table.emit_c(&mut stdout),
+        "json" => serde_json::to_writer(&mut stdout, &table)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(stdout)),
+        _ => fail(USAGE),
+    };
+    if let Err(error) = written {
+        fail(&error.to_string());
     }
 }
-
-fn main() {
-    println!("lr 1 generator");
-
-    let mut grammar = Grammar::new();
-
-    grammar.add_rule(
-        Rule::new(
-            Symbol::Nonterminal("EXPRESSION".to_string()),
-            Symbol::Nonterminal("EXPRESSION".to_string()),
-        )
-        .rhs(Symbol::Terminal("plus".to_string()))
-        .rhs(Symbol::Nonterminal("TERM".to_string())),
-    );
-    grammar.add_rule(Rule::new(
-        Symbol::Nonterminal("EXPRESSION".to_string()),
-        Symbol::Nonterminal("TERM".to_string()),
-    ));
-    grammar.add_rule(Rule::new(
-        Symbol::Nonterminal("TERM".to_string()),
-        Symbol::Terminal("number".to_string()),
-    ));
-
-    grammar.build(Symbol::Nonterminal("EXPRESSION".to_string()));
-
-    println!("{:?}", grammar.rules_lhs);
-    println!("{:?}", grammar.rules_len);
-}
@@ -0,0 +1,51 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Maps keyed by symbols, rules or item sets have no string form for JSON
+// keys, so they are written as a sequence of (key, value) pairs instead, in
+// key order so the same table is always written the same
+pub(crate) fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+    S: Serializer,
+{
+    let mut pairs: Vec<(&K, &V)> = map.iter().collect();
+    pairs.sort_by_key(|&(key, _)| key);
+    serializer.collect_seq(pairs)
+}
+
+pub(crate) fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    let pairs: Vec<(K, V)> = Vec::deserialize(deserializer)?;
+    Ok(pairs.into_iter().collect())
+}
+
+// Maps that keep their map form, only written in key order
+pub(crate) fn sorted<K, V, H, S>(map: &HashMap<K, V, H>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+    H: BuildHasher,
+    S: Serializer,
+{
+    serializer.collect_map(map.iter().collect::<BTreeMap<&K, &V>>())
+}
+
+pub(crate) fn sorted_rows<K, V, S>(rows: &[HashMap<K, V>], serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+    S: Serializer,
+{
+    serializer.collect_seq(
+        rows.iter()
+            .map(|row| row.iter().collect::<BTreeMap<&K, &V>>()),
+    )
+}
@@ -0,0 +1,530 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::grammar::RuleId;
+use crate::symbols::{Symbol, SymbolId};
+use crate::table::{Action, ParseTable};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tree {
+    Leaf(SymbolId),
+    Node(SymbolId, Vec<Tree>),
+}
+
+impl Tree {
+    pub fn symbol(&self) -> SymbolId {
+        match self {
+            Self::Leaf(symbol) | Self::Node(symbol, _) => *symbol,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    // Index of the offending token, the end marker sits right after the last one
+    pub position: usize,
+    pub token: SymbolId,
+    pub expected: Vec<SymbolId>,
+}
+
+type SemanticAction<V> = Box<dyn Fn(Vec<V>) -> V>;
+
+// Code run when a rule reduces, it gets the values of the rhs in order. Rules
+// without an action pass their first value through, empty ones give
+// `V::default()`, like the `reduce` of `emit_rust_actions`.
+pub struct Actions<V> {
+    actions: HashMap<RuleId, SemanticAction<V>>,
+}
+
+impl<V> Actions<V> {
+    pub fn new() -> Self {
+        Actions {
+            actions: HashMap::new(),
+        }
+    }
+
+    pub fn on(&mut self, rule: RuleId, action: impl Fn(Vec<V>) -> V + 'static) {
+        self.actions.insert(rule, Box::new(action));
+    }
+}
+
+impl<V: Default> Actions<V> {
+    fn apply(&self, rule: &RuleId, values: Vec<V>) -> V {
+        match self.actions.get(rule) {
+            Some(action) => action(values),
+            None => values.into_iter().next().unwrap_or_default(),
+        }
+    }
+}
+
+impl<V> Default for Actions<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Panic mode error recovery, as in yacc. On a syntax error the parser pops
+// states until one can shift the `error` terminal, shifts it, and then skips
+// tokens until one that synchronizes the nonterminals whose rules have just
+// seen `error`. Without synchronizing terminals for them, any token with an
+// action there will do. If an error comes before a real token got shifted
+// again, the token is dropped instead of being reported once more.
+pub struct Recovery {
+    error: SymbolId,
+    synchronizing: HashMap<SymbolId, BTreeSet<SymbolId>>,
+}
+
+impl Recovery {
+    pub fn new(error: SymbolId) -> Self {
+        Recovery {
+            error,
+            synchronizing: HashMap::new(),
+        }
+    }
+
+    pub fn synchronize(&mut self, nonterminal: SymbolId, terminals: &[SymbolId]) {
+        self.synchronizing
+            .entry(nonterminal)
+            .or_default()
+            .extend(terminals);
+    }
+}
+
+pub struct Parser<'a> {
+    table: &'a ParseTable,
+    start: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(table: &'a ParseTable) -> Self {
+        Parser { table, start: 0 }
+    }
+
+    // Parses `start` instead of the first start symbol of the table
+    pub fn with_start(table: &'a ParseTable, start: &Symbol) -> Option<Self> {
+        let start = table.start_state(start)?;
+        Some(Parser { table, start })
+    }
+
+    // `tokens` are terminal ids, without the end marker
+    pub fn parse(&self, tokens: &[SymbolId]) -> Result<Tree, SyntaxError> {
+        let tokens = tokens.iter().map(|&token| (token, Tree::Leaf(token)));
+        self.run(tokens, None, &mut Vec::new(), |rule, children| {
+            Tree::Node(rule.lhs, children)
+        })
+    }
+
+    // Like `parse`, but going on after the errors `recovery` can get past.
+    // The tree is there unless an error could not be recovered from, the
+    // errors include that last one.
+    pub fn parse_recovering(
+        &self,
+        tokens: &[SymbolId],
+        recovery: &Recovery,
+    ) -> (Option<Tree>, Vec<SyntaxError>) {
+        let tokens = tokens.iter().map(|&token| (token, Tree::Leaf(token)));
+        let error = || Tree::Leaf(recovery.error);
+        let mut errors = Vec::new();
+
+        let tree = self.run(
+            tokens,
+            Some((recovery, &error)),
+            &mut errors,
+            |rule, children| Tree::Node(rule.lhs, children),
+        );
+        match tree {
+            Ok(tree) => (Some(tree), errors),
+            Err(error) => {
+                errors.push(error);
+                (None, errors)
+            }
+        }
+    }
+
+    // Like `parse`, but every token carries a value and reductions run `actions`
+    pub fn evaluate<V: Default>(
+        &self,
+        tokens: impl IntoIterator<Item = (SymbolId, V)>,
+        actions: &Actions<V>,
+    ) -> Result<V, SyntaxError> {
+        self.run(tokens, None, &mut Vec::new(), |rule, values| {
+            actions.apply(rule, values)
+        })
+    }
+
+    // The terminals that end skipping after `error` was shifted into `state`
+    fn synchronizing(&self, state: usize, recovery: &Recovery) -> BTreeSet<SymbolId> {
+        let synchronizing: BTreeSet<SymbolId> = self
+            .table
+            .state_items(state)
+            .into_iter()
+            .flatten()
+            .filter(|item| item.position > 0 && item.rule.rhs[item.position - 1] == recovery.error)
+            .flat_map(|item| recovery.synchronizing.get(&item.rule.lhs))
+            .flatten()
+            .copied()
+            .collect();
+
+        if synchronizing.is_empty() {
+            self.table.expected(state).into_iter().collect()
+        } else {
+            synchronizing
+        }
+    }
+
+    // Recovered errors are pushed into `errors`, the one that stops the parse
+    // is returned
+    fn run<V>(
+        &self,
+        tokens: impl IntoIterator<Item = (SymbolId, V)>,
+        recovery: Option<(&Recovery, &dyn Fn() -> V)>,
+        errors: &mut Vec<SyntaxError>,
+        mut reduce: impl FnMut(&RuleId, Vec<V>) -> V,
+    ) -> Result<V, SyntaxError> {
+        let mut tokens = tokens.into_iter();
+        let mut states: Vec<usize> = Vec::from([self.start]);
+        let mut values: Vec<V> = Vec::new();
+        let mut position = 0;
+        let mut current = tokens.next();
+        let mut recovering = false;
+
+        loop {
+            let state = states[states.len() - 1];
+            let token = match &current {
+                // The end marker only comes after the last token, one in the
+                // input would accept before the rest of it is read
+                Some((token, _)) if *token == self.table.end => {
+                    let mut expected = self.table.expected(state);
+                    expected.retain(|&symbol| symbol != self.table.end);
+                    return Err(SyntaxError {
+                        position,
+                        token: *token,
+                        expected,
+                    });
+                }
+                Some((token, _)) => *token,
+                None => self.table.end,
+            };
+
+            match self.table.action(state, token) {
+                Some(&Action::Shift(next_state)) => {
+                    let (_, value) = current.take().expect("shifted the end marker");
+                    states.push(next_state);
+                    values.push(value);
+                    position += 1;
+                    current = tokens.next();
+                    recovering = false;
+                }
+                Some(&Action::Reduce(rule)) => {
+                    reduce_by(self.table, rule, &mut states, &mut values, &mut reduce);
+                }
+                Some(Action::Accept) => return Ok(values.pop().expect("accepted without a value")),
+                Some(Action::Goto(_)) | None => {
+                    let error = SyntaxError {
+                        position,
+                        token,
+                        expected: self.table.expected(state),
+                    };
+                    let (recovery, error_value) = match recovery {
+                        Some(recovery) => recovery,
+                        None => return Err(error),
+                    };
+
+                    if recovering {
+                        if current.is_none() {
+                            return Err(error);
+                        }
+                        position += 1;
+                        current = tokens.next();
+                        continue;
+                    }
+
+                    // The deepest point is the top of the stack, states are
+                    // popped until one of them shifts `error`
+                    let shift = states.iter().enumerate().rev().find_map(|(depth, &state)| {
+                        let next_state = self.table.shift(state, recovery.error)?;
+                        Some((depth, next_state))
+                    });
+                    let (depth, next_state) = match shift {
+                        Some(shift) => shift,
+                        None => return Err(error),
+                    };
+                    states.truncate(depth + 1);
+                    values.truncate(depth);
+                    errors.push(error);
+
+                    states.push(next_state);
+                    values.push(error_value());
+                    recovering = true;
+
+                    let synchronizing = self.synchronizing(next_state, recovery);
+                    while let Some((token, _)) = &current {
+                        if synchronizing.contains(token) {
+                            break;
+                        }
+                        position += 1;
+                        current = tokens.next();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ParseTable {
+    // Whether the first start symbol derives `tokens`, with only the state
+    // stack to keep, no tree or values. The end marker is only allowed after
+    // the last token, so one inside `tokens` is never recognized.
+    pub fn recognizes(&self, tokens: &[SymbolId]) -> bool {
+        if tokens.contains(&self.end) {
+            return false;
+        }
+        let mut states: Vec<usize> = Vec::from([0]);
+        let mut tokens = tokens.iter().copied().chain([self.end]);
+        let mut token = tokens.next();
+
+        while let Some(lookahead) = token {
+            let state = states[states.len() - 1];
+            match self.action(state, lookahead) {
+                Some(&Action::Shift(next_state)) => {
+                    states.push(next_state);
+                    token = tokens.next();
+                }
+                Some(&Action::Reduce(rule)) => {
+                    states.truncate(states.len() - self.rule_len(rule));
+                    match self.goto(states[states.len() - 1], self.rule_lhs(rule)) {
+                        Some(next_state) => states.push(next_state),
+                        None => return false,
+                    }
+                }
+                Some(Action::Accept) => return true,
+                Some(Action::Goto(_)) | None => return false,
+            }
+        }
+
+        false
+    }
+}
+
+// Pops the rhs of `rule` off both stacks and pushes the goto on its lhs
+fn reduce_by<V>(
+    table: &ParseTable,
+    rule: usize,
+    states: &mut Vec<usize>,
+    values: &mut Vec<V>,
+    reduce: &mut impl FnMut(&RuleId, Vec<V>) -> V,
+) {
+    let rule = &table.rule_ids[rule];
+    let (rhs_len, lhs) = (rule.rhs.len(), rule.lhs);
+    states.truncate(states.len() - rhs_len);
+    let children = values.split_off(values.len() - rhs_len);
+    values.push(reduce(rule, children));
+
+    let state = states[states.len() - 1];
+    match table.goto(state, lhs) {
+        Some(next_state) => states.push(next_state),
+        None => unreachable!("no goto on {lhs} from state {state}"),
+    }
+}
+
+// Like `Parser::evaluate`, but taking the tokens as they come instead of all
+// at once. Each token is reduced up to its shift as soon as it is fed.
+pub struct PushParser<'a, V> {
+    table: &'a ParseTable,
+    actions: &'a Actions<V>,
+    states: Vec<usize>,
+    values: Vec<V>,
+    position: usize,
+}
+
+impl<'a, V: Default> PushParser<'a, V> {
+    pub fn new(table: &'a ParseTable, actions: &'a Actions<V>) -> Self {
+        PushParser {
+            table,
+            actions,
+            states: Vec::from([0]),
+            values: Vec::new(),
+            position: 0,
+        }
+    }
+
+    // Parses `start` instead of the first start symbol of the table
+    pub fn with_start(
+        table: &'a ParseTable,
+        actions: &'a Actions<V>,
+        start: &Symbol,
+    ) -> Option<Self> {
+        let mut parser = Self::new(table, actions);
+        parser.states[0] = table.start_state(start)?;
+        Some(parser)
+    }
+
+    // After an error the parser is left as it was before `token`
+    pub fn feed(&mut self, token: SymbolId, value: V) -> Result<(), SyntaxError> {
+        if token == self.table.end {
+            return Err(self.error(token));
+        }
+        self.reduce_on(token)?;
+
+        match self.table.shift(self.state(), token) {
+            Some(next_state) => {
+                self.states.push(next_state);
+                self.values.push(value);
+                self.position += 1;
+                Ok(())
+            }
+            None => Err(self.error(token)),
+        }
+    }
+
+    pub fn end_of_input(mut self) -> Result<V, SyntaxError> {
+        let end = self.table.end;
+        self.reduce_on(end)?;
+
+        match self.table.action(self.state(), end) {
+            Some(Action::Accept) => Ok(self.values.pop().expect("accepted without a value")),
+            _ => Err(self.error(end)),
+        }
+    }
+
+    fn state(&self) -> usize {
+        self.states[self.states.len() - 1]
+    }
+
+    fn error(&self, token: SymbolId) -> SyntaxError {
+        SyntaxError {
+            position: self.position,
+            token,
+            expected: self.table.expected(self.state()),
+        }
+    }
+
+    // The terminals that `feed` or `end_of_input` would take next, `$` among
+    // them when the input may end here. Nothing is reduced to find them.
+    pub fn valid_next(&self) -> Vec<SymbolId> {
+        (0..self.table.symbols.len())
+            .filter(|&symbol| self.table.symbols.is_terminal(symbol))
+            .filter(|&symbol| self.reductions_before(symbol).is_some())
+            .collect()
+    }
+
+    // The rules reduced before `token` is shifted or accepted, worked out on
+    // a copy of the states. `None` when `token` is an error even after them.
+    fn reductions_before(&self, token: SymbolId) -> Option<Vec<usize>> {
+        let mut states = self.states.clone();
+        let mut reductions: Vec<usize> = Vec::new();
+        loop {
+            match self.table.action(states[states.len() - 1], token)? {
+                &Action::Reduce(rule) => {
+                    let lhs = self.table.rules_lhs[rule];
+                    states.truncate(states.len() - self.table.rules_len[rule]);
+                    states.push(self.table.goto(states[states.len() - 1], lhs)?);
+                    reductions.push(rule);
+                }
+                Action::Shift(_) | Action::Accept => return Some(reductions),
+                Action::Goto(_) => return None,
+            }
+        }
+    }
+
+    // The reductions are only done once `token` is known to be shifted or
+    // accepted after them, so that an error keeps the stacks as they were
+    fn reduce_on(&mut self, token: SymbolId) -> Result<(), SyntaxError> {
+        let reductions = self
+            .reductions_before(token)
+            .ok_or_else(|| self.error(token))?;
+
+        let actions = self.actions;
+        for rule in reductions {
+            reduce_by(
+                self.table,
+                rule,
+                &mut self.states,
+                &mut self.values,
+                &mut |rule, values| actions.apply(rule, values),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Actions, Parser, Recovery, SyntaxError, Tree};
+    use crate::grammar::Mode;
+    use crate::symbols::Symbol;
+    use crate::text::parse_grammar;
+
+    #[test]
+    fn empty_rule_without_action_gives_default() {
+        let mut grammar = parse_grammar("S -> A x\nA -> ").unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+        let x = table.symbols().id_by_name("x").unwrap();
+
+        let actions: Actions<i64> = Actions::new();
+        let value = Parser::new(&table).evaluate([(x, 7)], &actions);
+        assert_eq!(value, Ok(0));
+    }
+
+    #[test]
+    fn end_marker_inside_tokens_is_not_recognized() {
+        let mut grammar = parse_grammar("S -> S a | a").unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+        let a = table.symbols().id_by_name("a").unwrap();
+
+        assert!(table.recognizes(&[a, a]));
+        assert!(!table.recognizes(&[a, table.end, a]));
+    }
+
+    #[test]
+    fn end_marker_inside_tokens_is_an_error() {
+        let mut grammar = parse_grammar("S -> S a | a").unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+            .unwrap();
+        let a = table.symbols().id_by_name("a").unwrap();
+
+        let actions: Actions<i64> = Actions::new();
+        let error = Parser::new(&table)
+            .evaluate([(a, 1), (table.end, 0), (a, 2)], &actions)
+            .unwrap_err();
+        assert_eq!((error.position, error.token), (1, table.end));
+        assert_eq!(error.expected, [a]);
+    }
+
+    // The statement with the stray `x` becomes `S -> error semi` and the
+    // parse goes on with the next one
+    #[test]
+    fn recovers_at_the_next_semicolon() {
+        let mut grammar = parse_grammar("L -> L S | S\nS -> x semi | error semi").unwrap();
+        let table = grammar
+            .build(Symbol::Nonterminal("L".to_string()), Mode::Lalr1)
+            .unwrap();
+        let id = |name| table.symbols().id_by_name(name).unwrap();
+        let (x, semi, error) = (id("x"), id("semi"), id("error"));
+        let mut recovery = Recovery::new(error);
+        recovery.synchronize(id("S"), &[semi]);
+
+        let tokens = [x, semi, x, x, semi, x, semi];
+        let (tree, errors) = Parser::new(&table).parse_recovering(&tokens, &recovery);
+        assert_eq!(
+            errors,
+            [SyntaxError {
+                position: 3,
+                token: x,
+                expected: vec![semi],
+            }]
+        );
+
+        let statement = |first| Tree::Node(id("S"), vec![Tree::Leaf(first), Tree::Leaf(semi)]);
+        let list = |children| Tree::Node(id("L"), children);
+        let statements = list(vec![
+            list(vec![list(vec![statement(x)]), statement(error)]),
+            statement(x),
+        ]);
+        assert_eq!(tree, Some(statements));
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::grammar::RuleId;
+use crate::symbols::SymbolId;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Associativity {
+    Left,
+    Right,
+    Nonassoc,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Resolution {
+    Shift,
+    Reduce,
+    // Nonassoc, neither is taken
+    Error,
+}
+
+// What `build` does with the conflicts that precedence leaves, a shift/reduce
+// conflict only gets to the policy when the rule or the terminal has no
+// precedence.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    // Every conflict fails the build
+    #[default]
+    Report,
+    // Shift/reduce conflicts shift, which is the usual reading of a dangling
+    // else, reduce/reduce conflicts are still reported
+    PreferShift,
+    // Reduce/reduce conflicts reduce by the rule added first, shift/reduce
+    // conflicts are still reported
+    PreferFirstRule,
+    // Both of the above, what yacc does after warning
+    Yacc,
+}
+
+impl ConflictPolicy {
+    pub(crate) fn prefers_shift(self) -> bool {
+        matches!(self, Self::PreferShift | Self::Yacc)
+    }
+
+    pub(crate) fn prefers_first_rule(self) -> bool {
+        matches!(self, Self::PreferFirstRule | Self::Yacc)
+    }
+}
+
+// A shift/reduce conflict that precedence settled, what `build` does not
+// report as a conflict. Levels count from 1 in order of declaration.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ResolvedConflict {
+    pub state: usize,
+    pub symbol: SymbolId,
+    pub rule: RuleId,
+    pub rule_level: usize,
+    pub symbol_level: usize,
+    pub associativity: Associativity,
+    pub resolution: Resolution,
+}
+
+// Precedence side table, resolving shift/reduce conflicts the way yacc does.
+//
+// A rule takes the precedence of the terminal given with `Rule::precedence`,
+// or else of the last terminal in its rhs that has one. When shifting `t`
+// conflicts with reducing by a rule and both have a precedence, the higher one
+// wins. On a tie the associativity of the level decides: left reduces, right
+// shifts and nonassoc turns the entry into a syntax error. Conflicts where
+// either side has no precedence are still reported.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Precedence {
+    levels: usize,
+    #[serde(serialize_with = "crate::pairs::sorted")]
+    terminals: HashMap<SymbolId, (usize, Associativity)>,
+    #[serde(with = "crate::pairs")]
+    pub(crate) rules: HashMap<RuleId, SymbolId>,
+}
+
+impl Precedence {
+    pub(crate) fn declare(&mut self, associativity: Associativity, terminals: &[SymbolId]) {
+        self.levels += 1;
+        for &terminal in terminals {
+            self.terminals
+                .insert(terminal, (self.levels, associativity));
+        }
+    }
+
+    // The declared levels from the lowest, with their terminals by id
+    pub(crate) fn levels(&self) -> Vec<(Associativity, Vec<SymbolId>)> {
+        let mut levels: Vec<Option<(Associativity, Vec<SymbolId>)>> = vec![None; self.levels];
+        for (&terminal, &(level, associativity)) in &self.terminals {
+            levels[level - 1]
+                .get_or_insert((associativity, Vec::new()))
+                .1
+                .push(terminal);
+        }
+
+        levels
+            .into_iter()
+            .flatten()
+            .map(|(associativity, mut terminals)| {
+                terminals.sort();
+                (associativity, terminals)
+            })
+            .collect()
+    }
+
+    pub(crate) fn terminal(&self, terminal: SymbolId) -> Option<(usize, Associativity)> {
+        self.terminals.get(&terminal).copied()
+    }
+
+    pub(crate) fn rule(&self, rule: &RuleId) -> Option<(usize, Associativity)> {
+        match self.rules.get(rule) {
+            Some(&terminal) => self.terminal(terminal),
+            None => rule
+                .rhs
+                .iter()
+                .rev()
+                .find_map(|&symbol| self.terminal(symbol)),
+        }
+    }
+
+    pub(crate) fn resolve(&self, rule: &RuleId, terminal: SymbolId) -> Option<Resolution> {
+        let (rule_level, _) = self.rule(rule)?;
+        let (terminal_level, associativity) = self.terminal(terminal)?;
+
+        let resolution = if rule_level > terminal_level {
+            Resolution::Reduce
+        } else if rule_level < terminal_level {
+            Resolution::Shift
+        } else {
+            match associativity {
+                Associativity::Left => Resolution::Reduce,
+                Associativity::Right => Resolution::Shift,
+                Associativity::Nonassoc => Resolution::Error,
+            }
+        };
+
+        Some(resolution)
+    }
+}
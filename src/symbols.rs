@@ -0,0 +1,420 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, DefaultHasher, Hash, Hasher};
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+pub type SymbolId = usize;
+
+// Stands for the empty string in FIRST sets, it never names a real symbol
+pub const EPSILON: SymbolId = SymbolId::MAX;
+
+#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Symbol {
+    Terminal(String),
+    Nonterminal(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RenameError {
+    // No symbol has the old name
+    Unknown(String),
+    // A symbol of the same kind has the new name already
+    Taken(SymbolId),
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "no symbol named `{name}`"),
+            Self::Taken(symbol) => write!(f, "symbol {symbol} has that name already"),
+        }
+    }
+}
+
+impl Error for RenameError {}
+
+// The keys of `Symbols::index` are hashes already, they are used as they are
+#[derive(Default)]
+struct FingerprintHasher(u64);
+
+impl Hasher for FingerprintHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    fn write_u64(&mut self, fingerprint: u64) {
+        self.0 = fingerprint;
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "WrittenSymbols")]
+pub struct Symbols {
+    pub(crate) collection: Vec<Symbol>,
+    // Symbols by a hash of their kind and name, so each name is only stored
+    // in `collection`. The hashes can change between Rust releases, so the
+    // index is never written, it is built again from `collection`.
+    #[serde(skip)]
+    index: HashMap<u64, Vec<SymbolId>, BuildHasherDefault<FingerprintHasher>>,
+    // Exact spelling of the terminals that are not a token class
+    #[serde(serialize_with = "crate::pairs::sorted")]
+    literals: HashMap<SymbolId, String>,
+    // Patterns of the token classes, for `emit_lexer`
+    #[serde(serialize_with = "crate::pairs::sorted")]
+    patterns: HashMap<SymbolId, String>,
+    // Codes of an external lexer, both ways
+    #[serde(serialize_with = "crate::pairs::sorted")]
+    codes: HashMap<SymbolId, u32>,
+    #[serde(serialize_with = "crate::pairs::sorted")]
+    by_code: HashMap<u32, SymbolId>,
+    // The end of input marker, it is named `$` but left out of `index`, so a
+    // user terminal called `$` is a different symbol
+    end: Option<SymbolId>,
+}
+
+// `Symbols` as written, everything but the index
+#[derive(Deserialize)]
+struct WrittenSymbols {
+    collection: Vec<Symbol>,
+    literals: HashMap<SymbolId, String>,
+    patterns: HashMap<SymbolId, String>,
+    codes: HashMap<SymbolId, u32>,
+    by_code: HashMap<u32, SymbolId>,
+    end: Option<SymbolId>,
+}
+
+impl From<WrittenSymbols> for Symbols {
+    fn from(written: WrittenSymbols) -> Self {
+        let mut symbols = Symbols {
+            collection: written.collection,
+            index: HashMap::default(),
+            literals: written.literals,
+            patterns: written.patterns,
+            codes: written.codes,
+            by_code: written.by_code,
+            end: written.end,
+        };
+        for (id, symbol) in symbols.collection.iter().enumerate() {
+            if symbols.end != Some(id) {
+                symbols
+                    .index
+                    .entry(Self::fingerprint_of(symbol))
+                    .or_default()
+                    .push(id);
+            }
+        }
+        symbols
+    }
+}
+
+// The index follows from `collection`, only the order within its buckets
+// could tell two equal tables apart
+impl PartialEq for Symbols {
+    fn eq(&self, other: &Self) -> bool {
+        self.collection == other.collection
+            && self.literals == other.literals
+            && self.patterns == other.patterns
+            && self.codes == other.codes
+            && self.by_code == other.by_code
+            && self.end == other.end
+    }
+}
+
+impl Eq for Symbols {}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Self::with_capacity(0)
+    }
+
+    // Room for `capacity` symbols before growing
+    pub fn with_capacity(capacity: usize) -> Symbols {
+        Symbols {
+            collection: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity_and_hasher(capacity, BuildHasherDefault::default()),
+            literals: HashMap::new(),
+            patterns: HashMap::new(),
+            codes: HashMap::new(),
+            by_code: HashMap::new(),
+            end: None,
+        }
+    }
+
+    pub fn name(&self, symbol_id: SymbolId) -> &str {
+        if symbol_id == EPSILON {
+            return "ε";
+        }
+
+        match &self.collection[symbol_id] {
+            Symbol::Terminal(name) => name,
+            Symbol::Nonterminal(name) => name,
+        }
+    }
+
+    // The name as output shows it, a terminal sharing its name with a
+    // nonterminal is quoted like in the text format, `'x'`
+    pub fn display_name(&self, symbol_id: SymbolId) -> Cow<'_, str> {
+        let name = self.name(symbol_id);
+        let shared = self.is_terminal(symbol_id)
+            && !self.is_end(symbol_id)
+            && self.lookup(false, name).is_some();
+
+        if shared {
+            let escaped = name.replace('\\', "\\\\").replace('\'', "\\'");
+            Cow::Owned(format!("'{escaped}'"))
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.collection.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_empty()
+    }
+
+    fn fingerprint(terminal: bool, name: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (terminal, name).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn fingerprint_of(symbol: &Symbol) -> u64 {
+        match symbol {
+            Symbol::Terminal(name) => Self::fingerprint(true, name),
+            Symbol::Nonterminal(name) => Self::fingerprint(false, name),
+        }
+    }
+
+    fn lookup(&self, terminal: bool, name: &str) -> Option<SymbolId> {
+        self.index
+            .get(&Self::fingerprint(terminal, name))?
+            .iter()
+            .copied()
+            .find(|&id| match &self.collection[id] {
+                Symbol::Terminal(found) => terminal && found == name,
+                Symbol::Nonterminal(found) => !terminal && found == name,
+            })
+    }
+
+    pub fn id(&self, symbol: &Symbol) -> Option<SymbolId> {
+        match symbol {
+            Symbol::Terminal(name) => self.lookup(true, name),
+            Symbol::Nonterminal(name) => self.lookup(false, name),
+        }
+    }
+
+    // A terminal and a nonterminal may share a name, the terminal wins then.
+    // `$` is the end marker unless some user symbol has that name.
+    pub fn id_by_name(&self, name: &str) -> Option<SymbolId> {
+        self.lookup(true, name)
+            .or_else(|| self.lookup(false, name))
+            .or_else(|| self.end.filter(|_| name == "$"))
+    }
+
+    pub(crate) fn add_end(&mut self) -> SymbolId {
+        let id = self.collection.len();
+        self.collection.push(Symbol::Terminal("$".to_string()));
+        self.end = Some(id);
+        id
+    }
+
+    pub fn end(&self) -> Option<SymbolId> {
+        self.end
+    }
+
+    pub fn is_end(&self, symbol_id: SymbolId) -> bool {
+        self.end == Some(symbol_id)
+    }
+
+    pub fn add_symbol(&mut self, symbol: Symbol) -> SymbolId {
+        if let Some(id) = self.id(&symbol) {
+            return id;
+        }
+
+        let fingerprint = Self::fingerprint_of(&symbol);
+        let id = self.collection.len();
+        self.index.entry(fingerprint).or_default().push(id);
+        self.collection.push(symbol);
+        id
+    }
+
+    // Gives `symbol` the name `name`, it keeps its id and kind. The end marker
+    // is not renamed, there is no other way to name it.
+    pub fn rename(&mut self, symbol: SymbolId, name: &str) -> Result<(), RenameError> {
+        if self.is_end(symbol) {
+            return Err(RenameError::Unknown(self.name(symbol).to_string()));
+        }
+        let terminal = self.is_terminal(symbol);
+        match self.lookup(terminal, name) {
+            Some(found) if found == symbol => return Ok(()),
+            Some(found) => return Err(RenameError::Taken(found)),
+            None => {}
+        }
+
+        let old = Self::fingerprint(terminal, self.name(symbol));
+        if let Some(bucket) = self.index.get_mut(&old) {
+            bucket.retain(|&id| id != symbol);
+            if bucket.is_empty() {
+                self.index.remove(&old);
+            }
+        }
+        self.index
+            .entry(Self::fingerprint(terminal, name))
+            .or_default()
+            .push(symbol);
+        self.collection[symbol] = match terminal {
+            true => Symbol::Terminal(name.to_string()),
+            false => Symbol::Nonterminal(name.to_string()),
+        };
+        Ok(())
+    }
+
+    // Every symbol with its id, for another generated file to agree on them.
+    // Literals, patterns and codes are not part of it.
+    pub fn export(&self) -> Vec<(SymbolId, Symbol)> {
+        self.collection.iter().cloned().enumerate().collect()
+    }
+
+    // The symbols `export` gave, with the same ids, `end` being the id of the
+    // end marker if there was one. `None` unless the ids are 0, 1, 2... in
+    // any order, and each names a different symbol.
+    pub fn import(symbols: &[(SymbolId, Symbol)], end: Option<SymbolId>) -> Option<Symbols> {
+        let mut sorted: Vec<&(SymbolId, Symbol)> = symbols.iter().collect();
+        sorted.sort_by_key(|(id, _)| *id);
+
+        let mut imported = Symbols::with_capacity(sorted.len());
+        for (expected, (id, symbol)) in sorted.into_iter().enumerate() {
+            if *id != expected {
+                return None;
+            }
+
+            if end == Some(*id) {
+                imported.add_end();
+            } else if imported.add_symbol(symbol.clone()) != *id {
+                return None;
+            }
+        }
+
+        if end.is_some_and(|end| imported.end != Some(end)) {
+            return None;
+        }
+        Some(imported)
+    }
+
+    pub fn set_literal(&mut self, terminal: SymbolId, literal: &str) {
+        self.literals.insert(terminal, literal.to_string());
+    }
+
+    // `None` for token classes like `number`, `Some("+")` for `plus`
+    pub fn literal(&self, terminal: SymbolId) -> Option<&str> {
+        self.literals.get(&terminal).map(String::as_str)
+    }
+
+    pub fn set_pattern(&mut self, terminal: SymbolId, pattern: &str) {
+        self.patterns.insert(terminal, pattern.to_string());
+    }
+
+    pub fn pattern(&self, terminal: SymbolId) -> Option<&str> {
+        self.patterns.get(&terminal).map(String::as_str)
+    }
+
+    // Gives `terminal` the code an external lexer uses for it, a code names
+    // one terminal only, so another that had it loses it
+    pub fn set_code(&mut self, terminal: SymbolId, code: u32) {
+        if let Some(previous) = self.codes.insert(terminal, code) {
+            self.by_code.remove(&previous);
+        }
+        if let Some(previous) = self.by_code.insert(code, terminal)
+            && previous != terminal
+        {
+            self.codes.remove(&previous);
+        }
+    }
+
+    pub fn code(&self, terminal: SymbolId) -> Option<u32> {
+        self.codes.get(&terminal).copied()
+    }
+
+    pub fn terminal_by_code(&self, code: u32) -> Option<SymbolId> {
+        self.by_code.get(&code).copied()
+    }
+
+    pub fn is_terminal(&self, symbol_id: SymbolId) -> bool {
+        let symbol = match self.collection.get(symbol_id) {
+            Some(symbol) => symbol,
+            None => return false,
+        };
+
+        matches!(symbol, Symbol::Terminal(_))
+    }
+
+    pub fn is_nonterminal(&self, symbol_id: SymbolId) -> bool {
+        let symbol = match self.collection.get(symbol_id) {
+            Some(symbol) => symbol,
+            None => return false,
+        };
+
+        matches!(symbol, Symbol::Nonterminal(_))
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{Symbol, Symbols};
+
+    // Each name is stored once, in `collection`, so interning stays linear
+    #[test]
+    fn interns_tens_of_thousands_of_symbols() {
+        let count = 50_000;
+        let started = Instant::now();
+        let mut symbols = Symbols::with_capacity(count);
+        for i in 0..count {
+            assert_eq!(symbols.add_symbol(Symbol::Terminal(format!("t{i}"))), i);
+        }
+        for i in 0..count {
+            assert_eq!(symbols.add_symbol(Symbol::Terminal(format!("t{i}"))), i);
+            assert_eq!(symbols.id(&Symbol::Nonterminal(format!("t{i}"))), None);
+        }
+        eprintln!("interned {count} symbols twice in {:?}", started.elapsed());
+        assert_eq!(symbols.len(), count);
+    }
+
+    #[test]
+    fn index_is_rebuilt_when_read_back() {
+        let mut symbols = Symbols::new();
+        let number = symbols.add_symbol(Symbol::Terminal("number".to_string()));
+        let expression = symbols.add_symbol(Symbol::Nonterminal("E".to_string()));
+        let end = symbols.add_end();
+
+        let json = serde_json::to_string(&symbols).unwrap();
+        assert!(!json.contains("index"), "{json}");
+
+        let read_back: Symbols = serde_json::from_str(&json).unwrap();
+        assert!(read_back == symbols);
+        assert_eq!(read_back.id_by_name("number"), Some(number));
+        assert_eq!(
+            read_back.id(&Symbol::Nonterminal("E".to_string())),
+            Some(expression)
+        );
+        assert_eq!(read_back.id_by_name("$"), Some(end));
+    }
+}
@@ -0,0 +1,484 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::automaton::Transitions;
+use crate::grammar::{RuleId, Span};
+use crate::item::Item;
+use crate::precedence::{ConflictPolicy, Precedence, ResolvedConflict};
+use crate::symbols::{Symbol, SymbolId, Symbols};
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Goto(usize),
+    Shift(usize),
+    // The number of the reduced rule, an index into `rules_lhs` and `rules_len`
+    Reduce(usize),
+    Accept,
+}
+
+impl Action {
+    // `symbol` is the name of the symbol the action is taken on
+    pub fn format(&self, symbol: &str) -> String {
+        match self {
+            Self::Goto(next_state) => format!("goto({symbol}, {next_state})"),
+            Self::Shift(next_state) => format!("shift({symbol}, {next_state})"),
+            Self::Reduce(rule) => format!("reduce({symbol}, {rule})"),
+            Self::Accept => format!("accept({symbol})"),
+        }
+    }
+
+    pub fn render(&self, symbol: &str) {
+        println!("{}", self.format(symbol));
+    }
+}
+
+// Serializable so a generated table can be cached and reloaded as is
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseTable {
+    pub(crate) symbols: Symbols,
+    pub(crate) end: SymbolId,
+    // (start symbol, augmented S') pairs, the start state of each is its index
+    pub(crate) starts: Vec<(SymbolId, SymbolId)>,
+    pub(crate) precedence: Precedence,
+    pub(crate) policy: ConflictPolicy,
+    #[serde(with = "crate::pairs")]
+    pub(crate) spans: HashMap<RuleId, Span>,
+    #[serde(with = "crate::pairs")]
+    pub(crate) labels: HashMap<RuleId, String>,
+    // Item sets indexed by state number
+    pub(crate) states: Vec<BTreeSet<Item>>,
+    // The shifts and gotos of the collection, kept when a conflict settled
+    // against the shift takes it out of `actions`
+    #[serde(with = "crate::pairs")]
+    pub(crate) transitions: Transitions,
+    #[serde(serialize_with = "crate::pairs::sorted_rows")]
+    pub(crate) actions: Vec<HashMap<SymbolId, Action>>,
+    pub(crate) rules_lhs: Vec<usize>,
+    pub(crate) rules_len: Vec<usize>,
+    pub(crate) rule_ids: Vec<RuleId>,
+    pub(crate) resolved: Vec<ResolvedConflict>,
+    pub(crate) defaulted: Vec<Conflict>,
+}
+
+impl ParseTable {
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn end(&self) -> SymbolId {
+        self.end
+    }
+
+    // The augmented start symbol of the first start, S'
+    pub fn start(&self) -> SymbolId {
+        self.starts[0].1
+    }
+
+    pub fn start_state(&self, symbol: &Symbol) -> Option<usize> {
+        let symbol = self.symbols.id(symbol)?;
+        self.starts.iter().position(|&(start, _)| start == symbol)
+    }
+
+    pub(crate) fn augmented(&self) -> Vec<SymbolId> {
+        self.starts
+            .iter()
+            .map(|&(_, augmented)| augmented)
+            .collect()
+    }
+
+    pub fn states_len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn action(&self, state: usize, symbol: SymbolId) -> Option<&Action> {
+        self.actions.get(state)?.get(&symbol)
+    }
+
+    pub fn action_by_name(&self, state: usize, symbol: &str) -> Option<&Action> {
+        self.action(state, self.symbols.id_by_name(symbol)?)
+    }
+
+    // The terminal an external lexer means by `code`, as given to
+    // `Grammar::token_code`
+    pub fn terminal_by_code(&self, code: u32) -> Option<SymbolId> {
+        self.symbols.terminal_by_code(code)
+    }
+
+    // The state after a reduce to `nonterminal` uncovers `state`
+    pub fn goto(&self, state: usize, nonterminal: SymbolId) -> Option<usize> {
+        match self.action(state, nonterminal)? {
+            Action::Goto(next_state) => Some(*next_state),
+            _ => None,
+        }
+    }
+
+    pub fn shift(&self, state: usize, terminal: SymbolId) -> Option<usize> {
+        match self.action(state, terminal)? {
+            Action::Shift(next_state) => Some(*next_state),
+            _ => None,
+        }
+    }
+
+    // The number of the rule `state` reduces by on `terminal`
+    pub fn reduce(&self, state: usize, terminal: SymbolId) -> Option<usize> {
+        match self.action(state, terminal)? {
+            Action::Reduce(rule) => Some(*rule),
+            _ => None,
+        }
+    }
+
+    pub fn actions(&self, state: usize) -> Option<&HashMap<SymbolId, Action>> {
+        self.actions.get(state)
+    }
+
+    // Terminals with a shift, reduce or accept in `state`, sorted by id
+    pub fn expected(&self, state: usize) -> Vec<SymbolId> {
+        let mut expected: Vec<SymbolId> = self
+            .actions(state)
+            .into_iter()
+            .flatten()
+            .filter(|(_, action)| !matches!(action, Action::Goto(_)))
+            .map(|(&symbol, _)| symbol)
+            .collect();
+        expected.sort();
+        expected
+    }
+
+    // Every item with the number of its state, by state and then in the
+    // order of the items of a state, so it is the same on every run
+    pub fn items(&self) -> impl Iterator<Item = (usize, &Item)> {
+        self.states
+            .iter()
+            .enumerate()
+            .flat_map(|(state, set)| set.iter().map(move |item| (state, item)))
+    }
+
+    pub(crate) fn state_items(&self, state: usize) -> Option<&BTreeSet<Item>> {
+        self.states.get(state)
+    }
+
+    // The rule a `Reduce` action refers to
+    pub fn rule(&self, number: usize) -> Option<&RuleId> {
+        self.rule_ids.get(number)
+    }
+
+    // The label `Rule::label` gave the rule, if any
+    pub fn label(&self, rule: usize) -> Option<&str> {
+        self.labels
+            .get(self.rule_ids.get(rule)?)
+            .map(String::as_str)
+    }
+
+    // How many states a reduce by `rule` pops, panics when there is no such
+    // rule. The S' rules have numbers too, though they are only accepted.
+    pub fn rule_len(&self, rule: usize) -> usize {
+        self.rules_len[rule]
+    }
+
+    // The nonterminal whose goto follows a reduce by `rule`
+    pub fn rule_lhs(&self, rule: usize) -> SymbolId {
+        self.rules_lhs[rule]
+    }
+
+    // Numbers of the rules no state reduces by, dead parts of the grammar or
+    // rules whose every reduction lost a conflict. The S' rules are accepted
+    // instead and never count.
+    pub fn unreachable_rules(&self) -> Vec<usize> {
+        let reduced: BTreeSet<usize> = self
+            .actions
+            .iter()
+            .flat_map(HashMap::values)
+            .filter_map(|action| match action {
+                Action::Reduce(rule) => Some(*rule),
+                _ => None,
+            })
+            .collect();
+        let augmented = self.augmented();
+
+        (0..self.rule_ids.len())
+            .filter(|rule| !reduced.contains(rule))
+            .filter(|&rule| !augmented.contains(&self.rules_lhs[rule]))
+            .collect()
+    }
+
+    // Every shift/reduce conflict precedence decided, in state order, like
+    // the conflicts yacc reports as resolved in its verbose output
+    pub fn resolved_conflicts(&self) -> &[ResolvedConflict] {
+        &self.resolved
+    }
+
+    // The conflicts the `ConflictPolicy` settled instead of failing the build
+    pub fn defaulted_conflicts(&self) -> &[Conflict] {
+        &self.defaulted
+    }
+
+    pub fn conflict_summary(&self) -> ConflictSummary {
+        ConflictSummary::new(&self.defaulted)
+    }
+
+    pub fn rules_lhs(&self) -> &[usize] {
+        &self.rules_lhs
+    }
+
+    pub fn rules_len(&self) -> &[usize] {
+        &self.rules_len
+    }
+
+    // The ACTION and GOTO table as in textbooks, a row per state and a column
+    // per terminal, `$` last, then per nonterminal but S'
+    pub fn to_markdown(&self) -> String {
+        let augmented = self.augmented();
+        let (mut columns, nonterminals): (Vec<SymbolId>, Vec<SymbolId>) = (0..self.symbols.len())
+            .filter(|&symbol| symbol != self.end && !augmented.contains(&symbol))
+            .partition(|&symbol| self.symbols.is_terminal(symbol));
+        columns.push(self.end);
+        columns.extend(nonterminals);
+
+        let header: Vec<String> = columns
+            .iter()
+            .map(|&symbol| self.symbols.display_name(symbol).replace('|', "\\|"))
+            .collect();
+        let mut text = format!("| state | {} |\n", header.join(" | "));
+        text.push_str(&format!("|---{}|\n", "|---".repeat(columns.len())));
+
+        for state in 0..self.states_len() {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|&symbol| match self.action(state, symbol) {
+                    Some(Action::Shift(next_state)) => format!("s{next_state}"),
+                    Some(Action::Reduce(rule)) => format!("r{rule}"),
+                    Some(Action::Goto(next_state)) => format!("g{next_state}"),
+                    Some(Action::Accept) => "acc".to_string(),
+                    None => String::new(),
+                })
+                .collect();
+            text.push_str(&format!("| {state} | {} |\n", cells.join(" | ")));
+        }
+
+        text
+    }
+
+    pub fn format(&self) -> String {
+        format_states(&self.states, &self.actions, &self.symbols)
+    }
+
+    pub fn render(&self) {
+        print!("{}", self.format());
+    }
+}
+
+// Both kinds carry the kernel items of the state and the shortest symbols
+// that reach it from a start state
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Conflict {
+    // A shift and a reduce were assigned to the same (state, symbol) pair,
+    // `spans` has where the reduced rule was defined
+    ShiftReduce {
+        state: usize,
+        symbol: String,
+        actions: Vec<Action>,
+        spans: Vec<Span>,
+        items: Vec<Item>,
+        prefix: Vec<SymbolId>,
+    },
+    // Two completed items reduce on the same lookahead, `lookaheads` has
+    // every terminal both reduce on in the state, `symbol` among them
+    ReduceReduce {
+        state: usize,
+        symbol: String,
+        rules: Vec<RuleId>,
+        lookaheads: BTreeSet<SymbolId>,
+        spans: Vec<Span>,
+        items: Vec<Item>,
+        prefix: Vec<SymbolId>,
+    },
+}
+
+// Why a grammar gives no table
+#[derive(Debug, PartialEq, Eq)]
+pub enum GrammarError {
+    // A start that is not a symbol of the grammar
+    UndefinedSymbol(Symbol),
+    // A nonterminal some start derives that has no rule
+    NoRules(SymbolId),
+    Conflicts(Vec<Conflict>),
+    // The collection grew past this limit, set with `Grammar::max_states`
+    TooManyStates(usize),
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefinedSymbol(Symbol::Terminal(name) | Symbol::Nonterminal(name)) => {
+                write!(f, "undefined symbol `{name}`")
+            }
+            Self::NoRules(symbol) => write!(f, "nonterminal {symbol} has no rules"),
+            Self::Conflicts(conflicts) => write!(f, "{}", ConflictSummary::new(conflicts)),
+            Self::TooManyStates(limit) => write!(f, "more than {limit} states"),
+        }
+    }
+}
+
+impl Error for GrammarError {}
+
+impl Conflict {
+    pub fn format(&self, symbols: &Symbols) -> String {
+        let mut text = String::new();
+
+        match self {
+            Self::ShiftReduce {
+                state,
+                symbol,
+                actions,
+                spans,
+                ..
+            } => {
+                text.push_str(&format!(
+                    "shift/reduce conflict in state {state} on {symbol}\n"
+                ));
+                for action in actions {
+                    text.push_str(&format!("{}\n", action.format(symbol)));
+                }
+                for span in spans {
+                    text.push_str(&format!("rule defined at {span}\n"));
+                }
+            }
+            Self::ReduceReduce {
+                state,
+                symbol,
+                rules,
+                lookaheads,
+                spans,
+                ..
+            } => {
+                text.push_str(&format!(
+                    "reduce/reduce conflict in state {state} on {symbol}\n"
+                ));
+                for (rule, span) in rules.iter().zip(spans) {
+                    text.push_str(&format!(
+                        "rule defined at {span}: {}\n",
+                        rule.format(symbols)
+                    ));
+                }
+                let lookaheads: Vec<Cow<str>> = lookaheads
+                    .iter()
+                    .map(|&symbol| symbols.display_name(symbol))
+                    .collect();
+                text.push_str(&format!("both reduce on: {}\n", lookaheads.join(" ")));
+            }
+        }
+
+        let (Self::ShiftReduce { items, prefix, .. } | Self::ReduceReduce { items, prefix, .. }) =
+            self;
+        let prefix: Vec<Cow<str>> = prefix
+            .iter()
+            .map(|&symbol| symbols.display_name(symbol))
+            .collect();
+        text.push_str(&format!("reached after: {}\n", prefix.join(" ")));
+        for item in items {
+            text.push_str(&format!("    {}\n", item.format(symbols)));
+        }
+
+        text
+    }
+
+    pub fn render(&self, symbols: &Symbols) {
+        print!("{}", self.format(symbols));
+    }
+}
+
+// Conflicts counted by kind, like the line yacc ends its report with
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConflictSummary {
+    pub shift_reduce: usize,
+    pub reduce_reduce: usize,
+    // The states with any conflict, in order
+    pub states: Vec<usize>,
+}
+
+impl ConflictSummary {
+    pub fn new(conflicts: &[Conflict]) -> Self {
+        let mut summary = ConflictSummary::default();
+        let mut states: BTreeSet<usize> = BTreeSet::new();
+
+        for conflict in conflicts {
+            match conflict {
+                Conflict::ShiftReduce { state, .. } => {
+                    summary.shift_reduce += 1;
+                    states.insert(*state);
+                }
+                Conflict::ReduceReduce { state, .. } => {
+                    summary.reduce_reduce += 1;
+                    states.insert(*state);
+                }
+            }
+        }
+
+        summary.states = states.into_iter().collect();
+        summary
+    }
+}
+
+impl fmt::Display for ConflictSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} shift/reduce, {} reduce/reduce",
+            self.shift_reduce, self.reduce_reduce
+        )
+    }
+}
+
+fn format_states(
+    states: &[BTreeSet<Item>],
+    actions: &[HashMap<SymbolId, Action>],
+    symbols: &Symbols,
+) -> String {
+    let mut text = String::new();
+
+    for (number, set) in states.iter().enumerate() {
+        text.push_str(&format!("\n{number}\n"));
+        text.push_str("--- items ---\n");
+        for item in set {
+            text.push_str(&format!("{}\n", item.format(symbols)));
+        }
+        text.push_str("--- actions ---\n");
+        let mut row: Vec<(&SymbolId, &Action)> = actions[number].iter().collect();
+        row.sort_by_key(|&(&symbol, _)| symbol);
+        for (&symbol, action) in row {
+            text.push_str(&format!(
+                "{}\n",
+                action.format(&symbols.display_name(symbol))
+            ));
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParseTable;
+    use crate::grammar::Mode;
+    use crate::symbols::Symbol;
+    use crate::text::parse_grammar;
+
+    // Precedence settles the conflicts, so the resolved ones are written too
+    #[test]
+    fn json_reads_back_the_same_table() {
+        let text = "%left '+'\n%left '*'\nE -> E '+' E | E '*' E | n";
+        let table = parse_grammar(text)
+            .unwrap()
+            .build(Symbol::Nonterminal("E".to_string()), Mode::Lalr1)
+            .unwrap();
+        assert!(!table.resolved_conflicts().is_empty());
+
+        let json = serde_json::to_string(&table).unwrap();
+        let read_back: ParseTable = serde_json::from_str(&json).unwrap();
+        assert!(read_back == table);
+        assert_eq!(serde_json::to_string(&read_back).unwrap(), json);
+    }
+}
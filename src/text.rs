@@ -0,0 +1,595 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::iter::{Enumerate, Peekable};
+use std::str::{CharIndices, FromStr};
+use std::{error::Error, fmt, vec};
+
+use crate::grammar::{Grammar, Rule, Span};
+use crate::precedence::Associativity;
+use crate::symbols::{Symbol, SymbolId};
+
+// One nonterminal per line, alternatives separated by `|`:
+//
+//     EXPRESSION -> EXPRESSION plus TERM | TERM
+//     TERM -> number
+//
+// Names starting with an uppercase letter are nonterminals, anything else is a
+// terminal. A terminal can also be quoted, `'=='` or `'λ'`, to be named by
+// any text, with `\'` and `\\` standing for a quote and a backslash in it.
+// A quoted terminal is a literal, it matches exactly its text, and needs no
+// `%token`. An empty alternative stands for ε and `#` starts a comment.
+//
+// The EBNF groups `{ X }` for zero or more, `[ X ]` for optional and
+// `( A | B )` for grouping are also allowed, each becomes a fresh nonterminal
+// named after the lhs, the kind of group and its number within the lhs, the
+// numbers that would give a name the text already uses skipped:
+//
+//     A -> b [ c ] d        A -> b A_opt1 d
+//                           A_opt1 -> c | ε
+//     A -> { b }            A -> A_rep1
+//                           A_rep1 -> ε | A_rep1 b
+//     A -> ( b | c ) d      A -> A_group1 d
+//                           A_group1 -> b | c
+//
+// Lines starting with a directive declare instead of defining rules:
+//
+//     %start EXPRESSION     the start symbol
+//     %token number plus    the terminals, any other one is then an error
+//     %left plus            a precedence level, like yacc `%right` and
+//                           `%nonassoc` also exist
+pub fn parse_grammar(text: &str) -> Result<Grammar, ParseError> {
+    let mut grammar = Grammar::new();
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    // Every name in the text, the ones of the groups are kept out of it
+    let mut names: HashSet<String> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(line_index, line)| tokenize(line, line_index + 1).ok())
+        .flatten()
+        .filter_map(|(_, token)| match token {
+            Token::Name(name) => Some(name.to_string()),
+            Token::Quoted(text) => Some(text),
+            _ => None,
+        })
+        .collect();
+    let mut tokens_declared = false;
+    let mut declared: BTreeSet<String> = BTreeSet::new();
+    let mut terminals: Vec<(usize, usize, String)> = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let tokens = tokenize(line, line_number)?;
+
+        let mut tokens = tokens.into_iter();
+        let (span, lhs) = match tokens.next() {
+            Some((column, Token::Directive(directive))) => {
+                let mut names: Vec<(usize, Symbol)> = Vec::new();
+                for (column, token) in tokens {
+                    match token {
+                        Token::Name(name) => names.push((column, symbol(name))),
+                        Token::Quoted(text) => names.push((column, Symbol::Terminal(text))),
+                        _ => return Err(ParseError::new(line_number, column, "expected a name")),
+                    }
+                }
+
+                let associativity = match directive {
+                    "start" => {
+                        match names.as_slice() {
+                            [(_, start @ Symbol::Nonterminal(_))] => {
+                                grammar.declare_start(start.clone());
+                            }
+                            _ => {
+                                let message = "expected one nonterminal after `%start`";
+                                return Err(ParseError::new(line_number, column, message));
+                            }
+                        }
+                        continue;
+                    }
+                    "token" => None,
+                    "left" => Some(Associativity::Left),
+                    "right" => Some(Associativity::Right),
+                    "nonassoc" => Some(Associativity::Nonassoc),
+                    _ => {
+                        let message = format!("unknown directive `%{directive}`");
+                        return Err(ParseError::new(line_number, column, &message));
+                    }
+                };
+
+                let mut terminals: Vec<Symbol> = Vec::new();
+                for (column, name) in names {
+                    match name {
+                        Symbol::Terminal(name) => {
+                            declared.insert(name.clone());
+                            terminals.push(Symbol::Terminal(name));
+                        }
+                        Symbol::Nonterminal(_) => {
+                            return Err(ParseError::new(
+                                line_number,
+                                column,
+                                "expected a terminal",
+                            ));
+                        }
+                    }
+                }
+                match associativity {
+                    Some(associativity) => grammar.precedence(associativity, &terminals),
+                    None => tokens_declared = true,
+                }
+                continue;
+            }
+            Some((column, Token::Name(name))) if is_nonterminal(name) => {
+                let span = Span::Text {
+                    line: line_number,
+                    column,
+                };
+                (span, Symbol::Nonterminal(name.to_string()))
+            }
+            Some((column, _)) => {
+                return Err(ParseError::new(
+                    line_number,
+                    column,
+                    "expected a nonterminal",
+                ));
+            }
+            None => continue,
+        };
+
+        match tokens.next() {
+            Some((_, Token::Arrow)) => {}
+            Some((column, _)) => return Err(ParseError::new(line_number, column, "expected `->`")),
+            None => {
+                let column = line.chars().count() + 1;
+                return Err(ParseError::new(line_number, column, "expected `->`"));
+            }
+        }
+
+        let mut line = Line {
+            number: line_number,
+            end: line.chars().count() + 1,
+            lhs: lhs.clone(),
+            groups: &mut groups,
+            names: &mut names,
+            rules: Vec::new(),
+            terminals: Vec::new(),
+            literals: Vec::new(),
+        };
+        let alternatives = line.alternatives(&mut tokens.peekable(), None)?;
+        terminals.extend(
+            line.terminals
+                .drain(..)
+                .map(|(column, name)| (line_number, column, name)),
+        );
+
+        for alternative in alternatives {
+            grammar.add_rule(sequence(lhs.clone(), alternative).span(span));
+        }
+        // After the rules of the line, so the first rule still has the
+        // start symbol on the lhs
+        for rule in line.rules {
+            grammar.add_rule(rule.span(span));
+        }
+        for literal in line.literals {
+            grammar.literal(Symbol::Terminal(literal.clone()), &literal);
+        }
+    }
+
+    if tokens_declared {
+        let undeclared = terminals
+            .into_iter()
+            .find(|(_, _, name)| !declared.contains(name.as_str()));
+        if let Some((line, column, name)) = undeclared {
+            let message = format!("undeclared terminal `{name}`");
+            return Err(ParseError::new(line, column, &message));
+        }
+    }
+
+    Ok(grammar)
+}
+
+impl Grammar {
+    // The grammar in the format `parse_grammar` reads, with the alternatives
+    // of each lhs on one line and the lines in the order their first rule was
+    // added. Groups come out as the nonterminals they became and the S' rules
+    // of earlier builds are left out. The format has no way to write a
+    // nonterminal whose name does not start with an uppercase letter, reading
+    // it back would give a terminal, so such a name is an error.
+    pub fn to_grammar_text(&self) -> Result<String, WriteError> {
+        let mut text = String::new();
+
+        if let Some(Symbol::Nonterminal(start)) = self.declared_start() {
+            text.push_str(&format!("%start {}\n", nonterminal_text(start)?));
+        }
+        for (associativity, terminals) in self.precedence.levels() {
+            let directive = match associativity {
+                Associativity::Left => "left",
+                Associativity::Right => "right",
+                Associativity::Nonassoc => "nonassoc",
+            };
+            let terminals: Vec<String> = terminals
+                .into_iter()
+                .map(|terminal| self.symbol_text(terminal))
+                .collect::<Result<_, _>>()?;
+            text.push_str(&format!("%{directive} {}\n", terminals.join(" ")));
+        }
+
+        let mut lhs_order: Vec<SymbolId> = Vec::new();
+        for &lhs in &self.rules_lhs {
+            if !lhs_order.contains(&lhs) && !self.is_augmented(lhs) {
+                lhs_order.push(lhs);
+            }
+        }
+        for lhs in lhs_order {
+            let alternatives: Vec<String> = self.rules[&lhs]
+                .iter()
+                .map(|rhs| {
+                    rhs.iter()
+                        .map(|&symbol| Ok(format!(" {}", self.symbol_text(symbol)?)))
+                        .collect::<Result<String, WriteError>>()
+                })
+                .collect::<Result<_, _>>()?;
+            text.push_str(&format!(
+                "{} ->{}\n",
+                self.symbol_text(lhs)?,
+                alternatives.join(" |")
+            ));
+        }
+
+        Ok(text)
+    }
+
+    // Terminals that would not read back as one are quoted, nonterminals
+    // that would not are an error
+    fn symbol_text(&self, symbol: SymbolId) -> Result<String, WriteError> {
+        let name = self.symbols.name(symbol);
+        if self.symbols.is_nonterminal(symbol) {
+            return nonterminal_text(name);
+        }
+        // Literals stay quoted, so reading the text back keeps them literals
+        if !name.is_empty()
+            && self.symbols.literal(symbol) != Some(name)
+            && !name.starts_with('\'')
+            && !is_nonterminal(name)
+            && name.chars().all(is_name_char)
+        {
+            return Ok(name.to_string());
+        }
+
+        let escaped = name.replace('\\', "\\\\").replace('\'', "\\'");
+        Ok(format!("'{escaped}'"))
+    }
+}
+
+fn nonterminal_text(name: &str) -> Result<String, WriteError> {
+    match is_nonterminal(name) && name.chars().all(is_name_char) {
+        true => Ok(name.to_string()),
+        false => Err(WriteError::Nonterminal(name.to_string())),
+    }
+}
+
+// Why `to_grammar_text` cannot write a grammar
+#[derive(Debug, PartialEq, Eq)]
+pub enum WriteError {
+    // A nonterminal with this name would read back as a terminal, or not at
+    // all
+    Nonterminal(String),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nonterminal(name) => {
+                write!(
+                    f,
+                    "nonterminal `{name}` has no name the text format can read"
+                )
+            }
+        }
+    }
+}
+
+impl Error for WriteError {}
+
+impl FromStr for Grammar {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_grammar(text)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, column: usize, message: &str) -> Self {
+        ParseError {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+fn sequence(lhs: Symbol, symbols: Vec<Symbol>) -> Rule {
+    symbols
+        .into_iter()
+        .fold(Rule::empty(lhs), |rule, symbol| rule.rhs(symbol))
+}
+
+type Tokens<'a> = Peekable<vec::IntoIter<(usize, Token<'a>)>>;
+
+// The rhs of one line, with the rules of its groups kept aside and the
+// terminals it uses by column
+struct Line<'a> {
+    number: usize,
+    end: usize,
+    lhs: Symbol,
+    groups: &'a mut HashMap<String, usize>,
+    names: &'a mut HashSet<String>,
+    rules: Vec<Rule>,
+    terminals: Vec<(usize, String)>,
+    literals: Vec<String>,
+}
+
+impl Line<'_> {
+    fn group(&mut self, kind: &str) -> Symbol {
+        let lhs = match &self.lhs {
+            Symbol::Terminal(name) | Symbol::Nonterminal(name) => name,
+        };
+        let count = self.groups.entry(lhs.clone()).or_default();
+        loop {
+            *count += 1;
+            let name = format!("{lhs}_{kind}{count}");
+            if self.names.insert(name.clone()) {
+                return Symbol::Nonterminal(name);
+            }
+        }
+    }
+
+    // Alternatives up to the `close` bracket, or to the end of the line
+    fn alternatives(
+        &mut self,
+        tokens: &mut Tokens<'_>,
+        close: Option<char>,
+    ) -> Result<Vec<Vec<Symbol>>, ParseError> {
+        let mut alternatives: Vec<Vec<Symbol>> = Vec::from([Vec::new()]);
+
+        loop {
+            let (column, token) = match tokens.next() {
+                Some(token) => token,
+                None => match close {
+                    Some(close) => {
+                        let message = format!("expected `{close}`");
+                        return Err(ParseError::new(self.number, self.end, &message));
+                    }
+                    None => return Ok(alternatives),
+                },
+            };
+
+            let symbol = match token {
+                Token::Name(name) => {
+                    if !is_nonterminal(name) {
+                        self.terminals.push((column, name.to_string()));
+                    }
+                    symbol(name)
+                }
+                Token::Quoted(text) => {
+                    self.literals.push(text.clone());
+                    Symbol::Terminal(text)
+                }
+                Token::Bar => {
+                    alternatives.push(Vec::new());
+                    continue;
+                }
+                Token::Open(open) => self.open(tokens, open)?,
+                Token::Close(found) if Some(found) == close => return Ok(alternatives),
+                Token::Close(found) => {
+                    let message = format!("unexpected `{found}`");
+                    return Err(ParseError::new(self.number, column, &message));
+                }
+                Token::Arrow => {
+                    return Err(ParseError::new(self.number, column, "unexpected `->`"));
+                }
+                Token::Directive(_) => {
+                    return Err(ParseError::new(self.number, column, "unexpected directive"));
+                }
+            };
+
+            if let Some(alternative) = alternatives.last_mut() {
+                alternative.push(symbol);
+            }
+        }
+    }
+
+    fn open(&mut self, tokens: &mut Tokens<'_>, open: char) -> Result<Symbol, ParseError> {
+        let (kind, close) = match open {
+            '{' => ("rep", '}'),
+            '[' => ("opt", ']'),
+            _ => ("group", ')'),
+        };
+        let alternatives = self.alternatives(tokens, Some(close))?;
+        let group = self.group(kind);
+
+        match open {
+            '{' => {
+                self.rules.push(Rule::empty(group.clone()));
+                for alternative in alternatives {
+                    let rhs = Vec::from([group.clone()]).into_iter().chain(alternative);
+                    self.rules.push(sequence(group.clone(), rhs.collect()));
+                }
+            }
+            _ => {
+                let optional = open == '[' && !alternatives.iter().any(Vec::is_empty);
+                for alternative in alternatives {
+                    self.rules.push(sequence(group.clone(), alternative));
+                }
+                if optional {
+                    self.rules.push(Rule::empty(group.clone()));
+                }
+            }
+        }
+
+        Ok(group)
+    }
+}
+
+enum Token<'a> {
+    Name(&'a str),
+    Arrow,
+    Bar,
+    Open(char),
+    Close(char),
+    Directive(&'a str),
+    Quoted(String),
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '\''
+}
+
+fn is_nonterminal(name: &str) -> bool {
+    name.starts_with(char::is_uppercase)
+}
+
+fn symbol(name: &str) -> Symbol {
+    if is_nonterminal(name) {
+        Symbol::Nonterminal(name.to_string())
+    } else {
+        Symbol::Terminal(name.to_string())
+    }
+}
+
+// Consumes the rest of a name starting with `first` at byte `start`
+fn name_end(chars: &mut Peekable<Enumerate<CharIndices<'_>>>, start: usize, first: char) -> usize {
+    let mut end = start + first.len_utf8();
+    while let Some((_, (index, c))) = chars.next_if(|&(_, (_, c))| is_name_char(c)) {
+        end = index + c.len_utf8();
+    }
+    end
+}
+
+// Tokens paired with their 1-based column
+fn tokenize(line: &str, line_number: usize) -> Result<Vec<(usize, Token<'_>)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().enumerate().peekable();
+
+    while let Some((column, (start, c))) = chars.next() {
+        let column = column + 1;
+
+        match c {
+            '#' => break,
+            '|' => tokens.push((column, Token::Bar)),
+            '{' | '[' | '(' => tokens.push((column, Token::Open(c))),
+            '}' | ']' | ')' => tokens.push((column, Token::Close(c))),
+            '-' if matches!(chars.peek(), Some((_, (_, '>')))) => {
+                chars.next();
+                tokens.push((column, Token::Arrow));
+            }
+            c if c.is_whitespace() => {}
+            '%' => match chars.next_if(|&(_, (_, c))| is_name_char(c)) {
+                Some((_, (start, c))) => {
+                    let end = name_end(&mut chars, start, c);
+                    tokens.push((column, Token::Directive(&line[start..end])));
+                }
+                None => return Err(ParseError::new(line_number, column, "expected a directive")),
+            },
+            '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, (_, '\''))) => break,
+                        Some((_, (_, '\\'))) => match chars.next() {
+                            Some((_, (_, c @ ('\'' | '\\')))) => text.push(c),
+                            Some((escape, _)) => {
+                                let message = "expected `'` or `\\` after `\\`";
+                                return Err(ParseError::new(line_number, escape + 1, message));
+                            }
+                            None => {
+                                let message = "unterminated quoted terminal";
+                                return Err(ParseError::new(line_number, column, message));
+                            }
+                        },
+                        Some((_, (_, c))) => text.push(c),
+                        None => {
+                            let message = "unterminated quoted terminal";
+                            return Err(ParseError::new(line_number, column, message));
+                        }
+                    }
+                }
+                if text.is_empty() {
+                    return Err(ParseError::new(
+                        line_number,
+                        column,
+                        "empty quoted terminal",
+                    ));
+                }
+                tokens.push((column, Token::Quoted(text)));
+            }
+            c if is_name_char(c) => {
+                let end = name_end(&mut chars, start, c);
+                tokens.push((column, Token::Name(&line[start..end])));
+            }
+            c => {
+                let message = format!("unexpected character `{c}`");
+                return Err(ParseError::new(line_number, column, &message));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WriteError, parse_grammar};
+    use crate::grammar::{Grammar, Rule};
+    use crate::symbols::Symbol;
+
+    #[test]
+    fn groups_skip_names_the_text_uses() {
+        let grammar = parse_grammar("A -> b [ c ] A_opt1\nA_opt1 -> d").unwrap();
+
+        let rules = grammar.format_rules();
+        assert!(rules.contains("A -> b A_opt2 A_opt1"), "{rules}");
+        assert!(rules.contains("A_opt1 -> d\n"), "{rules}");
+    }
+
+    #[test]
+    fn grammar_text_reads_back_the_same() {
+        let text = "%left plus\nE -> E plus T | T | 'T'\nT -> number | ( E ) | \n";
+        let written = parse_grammar(text).unwrap().to_grammar_text().unwrap();
+        let read_back = parse_grammar(&written).unwrap();
+
+        assert_eq!(read_back.to_grammar_text().unwrap(), written);
+        assert_eq!(
+            read_back.format_rules(),
+            parse_grammar(text).unwrap().format_rules()
+        );
+    }
+
+    #[test]
+    fn nonterminals_the_text_cannot_name_are_an_error() {
+        let mut grammar = Grammar::new();
+        let start = Symbol::Nonterminal("S".to_string());
+        let lowercase = Symbol::Nonterminal("a".to_string());
+        grammar
+            .add_rule(Rule::new(start, lowercase.clone()).rhs(Symbol::Terminal("a".to_string())));
+        grammar.add_rule(Rule::new(lowercase, Symbol::Terminal("b".to_string())));
+
+        assert_eq!(
+            grammar.to_grammar_text(),
+            Err(WriteError::Nonterminal("a".to_string()))
+        );
+    }
+}
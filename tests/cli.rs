@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Writes `grammar` to a file named `name` and runs the binary on it with `args`
+fn lrgen(name: &str, grammar: &str, args: &[&str]) -> Output {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, grammar).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_lrgen"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn prints_the_table() {
+    let output = lrgen("cc.txt", "S -> C C\nC -> c C | d\n", &["--mode", "lalr"]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let states = stdout
+        .lines()
+        .filter(|line| line.parse::<usize>().is_ok())
+        .count();
+    assert_eq!(states, 7);
+    assert!(stdout.contains("[S' -> · S, $]"));
+    assert!(stdout.contains("accept($)"));
+    assert!(stdout.contains("shift(d, 4)"));
+}
+
+#[test]
+fn conflicts_exit_with_an_error() {
+    let output = lrgen("ambiguous.txt", "E -> E '+' E | n\n", &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("shift/reduce conflict in state 4 on +"));
+    assert!(stderr.ends_with("1 shift/reduce, 0 reduce/reduce\n"));
+}
+
+#[test]
+fn unknown_flags_print_the_usage() {
+    let output = lrgen("usage.txt", "S -> a\n", &["--verbose"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("lrgen: usage: lrgen"));
+}
+
+#[test]
+fn max_states_exits_with_an_error() {
+    let grammar = "S -> C C\nC -> c C | d\n";
+    let output = lrgen(
+        "limit.txt",
+        grammar,
+        &["--mode", "lalr", "--max-states", "3"],
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with(": more than 3 states\n"));
+
+    let output = lrgen(
+        "limit.txt",
+        grammar,
+        &["--mode", "lalr", "--max-states", "10"],
+    );
+    assert!(output.status.success());
+}
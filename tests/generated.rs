@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use lrgen::{Mode, ParseTable, Symbol, parse_grammar};
+
+fn table(text: &str, start: &str) -> ParseTable {
+    parse_grammar(text)
+        .unwrap()
+        .build(Symbol::Nonterminal(start.to_string()), Mode::Lalr1)
+        .unwrap()
+}
+
+// Compiles `module` as `generated` with `main` and runs it, giving what it
+// printed
+fn run(name: &str, module: &[u8], main: &str) -> String {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("generated.rs"), module).unwrap();
+    fs::write(dir.join("main.rs"), format!("mod generated;\n\n{main}")).unwrap();
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let compiled = Command::new(rustc)
+        .args(["--edition", "2024", "-A", "dead_code", "-o"])
+        .arg(dir.join("main"))
+        .arg(dir.join("main.rs"))
+        .output()
+        .unwrap();
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+
+    let ran = Command::new(dir.join("main")).output().unwrap();
+    assert!(
+        ran.status.success(),
+        "{}",
+        String::from_utf8_lossy(&ran.stderr)
+    );
+    String::from_utf8(ran.stdout).unwrap()
+}
+
+#[test]
+fn emitted_parser_parses_number_plus_number() {
+    let table = table("E -> E plus T | T\nT -> number", "E");
+    let plus_rule = (0..table.rules_len().len())
+        .filter_map(|rule| table.rule(rule))
+        .find(|rule| rule.rhs.len() == 3)
+        .unwrap()
+        .clone();
+    let actions = HashMap::from([(plus_rule, "$1 + $3".to_string())]);
+    let mut module = Vec::new();
+    table
+        .emit_rust_actions(&mut module, "i64", &actions)
+        .unwrap();
+
+    let id = |name: &str| table.symbols().id_by_name(name).unwrap();
+    let main = format!(
+        r#"use generated::{{END, evaluate, parse, parse_tree}};
+
+fn main() {{
+    let (number, plus) = ({number}, {plus});
+    println!("{{}}", parse([number, plus, number]).is_ok());
+    println!("{{}}", parse_tree([number, plus, number]).is_ok());
+    println!("{{:?}}", evaluate([(number, 2), (plus, 0), (number, 3)]));
+    println!("{{:?}}", parse([number, plus]).map_err(|error| error.position));
+    println!("{{:?}}", parse([number, END, plus]).map_err(|error| error.position));
+    println!("{{:?}}", parse_tree([number, END, plus]).map_err(|error| error.position));
+    println!("{{:?}}", evaluate([(number, 2), (END, 0), (plus, 0)]).map_err(|error| error.position));
+}}
+"#,
+        number = id("number"),
+        plus = id("plus"),
+    );
+
+    let printed = run("number_plus_number", &module, &main);
+    assert_eq!(
+        printed,
+        "true\ntrue\nOk(5)\nErr(2)\nErr(1)\nErr(1)\nErr(1)\n"
+    );
+}
+
+#[test]
+fn token_enum_compiles_for_keyword_names() {
+    let table = table("S -> self 'Self' end", "S");
+    let mut module = Vec::new();
+    table.emit_token_enum(&mut module).unwrap();
+
+    let main = "fn main() {
+    use generated::Token;
+    println!(\"{:?}\", [Token::TSelf, Token::TSelf3, Token::End4, Token::End].map(|token| token as usize));
+}
+";
+    let id = |name: &str| table.symbols().id_by_name(name).unwrap();
+    let printed = run("keyword_tokens", &module, main);
+    assert_eq!(
+        printed,
+        format!("{:?}\n", [id("self"), id("Self"), id("end"), id("$")])
+    );
+}
+
+// `iffy` is longer as a name than the keyword `if` it starts with, `\d+`
+// takes all of `12` and a tie between `if` and a name goes to the keyword
+#[test]
+fn emitted_lexer_takes_the_longest_match() {
+    let mut grammar = parse_grammar("S -> E | if name\nE -> E '+' number | number").unwrap();
+    grammar.literal(Symbol::Terminal("if".to_string()), "if");
+    grammar.pattern(Symbol::Terminal("name".to_string()), "[a-z]+");
+    grammar.pattern(Symbol::Terminal("number".to_string()), "\\d+");
+    let table = grammar
+        .build(Symbol::Nonterminal("S".to_string()), Mode::Lalr1)
+        .unwrap();
+    let mut module = Vec::new();
+    table.emit_rust(&mut module).unwrap();
+    table.emit_lexer(&mut module).unwrap();
+
+    let main = r#"use generated::{parse, tokenize};
+
+fn main() {
+    for input in ["12 + 3", "if iffy", "if if", "1 ? 2"] {
+        let tokens = tokenize(input);
+        let parsed = tokens.as_ref().map(|tokens| parse(tokens.iter().copied()).is_ok());
+        println!("{tokens:?} {parsed:?}");
+    }
+}
+"#;
+    let id = |name: &str| table.symbols().id_by_name(name).unwrap();
+    let (number, plus, keyword, name) = (id("number"), id("+"), id("if"), id("name"));
+    let printed = run("longest_match", &module, main);
+    assert_eq!(
+        printed,
+        format!(
+            "Ok({:?}) Ok(true)\nOk({:?}) Ok(true)\nOk({:?}) Ok(false)\nErr(LexError {{ position: 2 }}) Err(LexError {{ position: 2 }})\n",
+            [number, plus, number],
+            [keyword, name],
+            [keyword, keyword],
+        )
+    );
+}